@@ -0,0 +1,173 @@
+// Client-side helpers for building staking instructions off-chain.
+use anchor_lang::prelude::*;
+use anchor_lang::InstructionData;
+use solana_program::instruction::Instruction;
+use solana_program::program_pack::Pack;
+
+use crate::{
+    EmissionStep, StakingPool, BASIS_POINTS, MAX_EMISSION_STEPS, MINT_AUTHORITY_PDA_SEED, RARITY_PDA_SEED, REVENUE_VAULT_PDA_SEED,
+    REWARD_STREAM_GROUP_LEN, REWARD_VAULT_PDA_SEED, STAKE_MANY_GROUP_LEN, STAKE_PDA_SEED, STAKING_ACCOUNT_PDA_SEED, USER_SUMMARY_PDA_SEED,
+};
+
+// Maximum size of a serialized transaction, in bytes
+const TRANSACTION_SIZE_LIMIT: usize = 1232;
+
+// Bytes of a stake_many transaction other than its account keys and indexes: the owner's signature and its count, the message header,
+// the account key count, the recent blockhash, the instruction count, the program id index, and the account index and data lengths
+// with the 10 bytes of data (discriminator and lock_days)
+const STAKE_MANY_FIXED_BYTES: usize = 1 + 64 + 3 + 1 + 32 + 1 + 1 + 1 + 1 + 10;
+
+// Number of tokens staked per stake_many transaction, as many as fit in one. The program id takes a 32 byte key, and every other account,
+// the seven StakeMany accounts and the STAKE_MANY_GROUP_LEN accounts of each token, a 32 byte key and a 1 byte index: 378 + 198 bytes
+// per token, so 4 tokens. Only the transaction size is accounted for here, not the compute units used by a chunk.
+pub const STAKE_MANY_CHUNK_SIZE: usize =
+    (TRANSACTION_SIZE_LIMIT - STAKE_MANY_FIXED_BYTES - 32 - 33 * 7) / (33 * STAKE_MANY_GROUP_LEN);
+
+// A token to be staked with stake_many
+pub struct StakeManyToken {
+    pub staking_mint: Pubkey,
+    pub owner_staking_token_account: Pubkey,
+}
+
+// Build the stake_many instructions for a set of tokens locked for lock_days days, split into chunks of STAKE_MANY_CHUNK_SIZE tokens.
+// Each instruction should be sent in its own transaction, signed by the owner.
+pub fn stake_many_instructions(
+    staking_token_owner: &Pubkey,
+    owner_reward_token_account: &Pubkey,
+    pool: &Pubkey,
+    lock_days: u16,
+    tokens: &[StakeManyToken],
+) -> Vec<Instruction> {
+    tokens
+        .chunks(STAKE_MANY_CHUNK_SIZE)
+        .map(|chunk| {
+            let mut accounts = crate::accounts::StakeMany {
+                staking_token_owner: *staking_token_owner,
+                owner_reward_token_account: *owner_reward_token_account,
+                user_stake_summary: user_stake_summary_address(pool, staking_token_owner),
+                pool: *pool,
+                system_program: solana_program::system_program::ID,
+                rent: solana_program::sysvar::rent::ID,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None);
+
+            accounts.reserve(chunk.len() * STAKE_MANY_GROUP_LEN);
+
+            for token in chunk {
+                let (staking_mint_metadata, _) = mpl_token_metadata::pda::find_metadata_account(&token.staking_mint);
+                let staking_account = stake_account_address(pool, &token.staking_mint);
+                let (rarity_record, _) = Pubkey::find_program_address(
+                    &[RARITY_PDA_SEED, pool.as_ref(), token.staking_mint.as_ref()],
+                    &crate::ID,
+                );
+                let (vault_account, _) = Pubkey::find_program_address(
+                    &[STAKING_ACCOUNT_PDA_SEED, staking_account.as_ref(), token.staking_mint.as_ref()],
+                    &crate::ID,
+                );
+
+                accounts.push(AccountMeta::new_readonly(token.staking_mint, false));
+                accounts.push(AccountMeta::new_readonly(staking_mint_metadata, false));
+                accounts.push(AccountMeta::new_readonly(rarity_record, false));
+                accounts.push(AccountMeta::new(token.owner_staking_token_account, false));
+                accounts.push(AccountMeta::new(vault_account, false));
+                accounts.push(AccountMeta::new(staking_account, false));
+            }
+
+            Instruction {
+                program_id: crate::ID,
+                accounts,
+                data: crate::instruction::StakeMany { lock_days }.data(),
+            }
+        })
+        .collect()
+}
+
+// Address of the StakeAccount (PDA) recording a token staked in a pool. A token can only be staked once per pool at a time.
+pub fn stake_account_address(pool: &Pubkey, staking_mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[STAKE_PDA_SEED, pool.as_ref(), staking_mint.as_ref()], &crate::ID).0
+}
+
+// Address of the UserStakeSummary (PDA) of an owner in a pool, created with the owner's first stake
+pub fn user_stake_summary_address(pool: &Pubkey, owner: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[USER_SUMMARY_PDA_SEED, pool.as_ref(), owner.as_ref()], &crate::ID).0
+}
+
+// Address of a pool's vault (PDA) for a reward mint, created by init_reward_vault or add_reward_stream and funded through fund_pool
+pub fn reward_vault_address(pool: &Pubkey, reward_mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[REWARD_VAULT_PDA_SEED, pool.as_ref(), reward_mint.as_ref()], &crate::ID).0
+}
+
+// Balance of a pool's reward vault in base units of the reward token, read from the data of the account at reward_vault_address
+pub fn reward_vault_balance(reward_vault_data: &[u8]) -> std::result::Result<u64, ProgramError> {
+    Ok(spl_token::state::Account::unpack(reward_vault_data)?.amount)
+}
+
+// Build the remaining accounts paying a pool's reward streams, for collect, collect_full, unstake and claim_and_unstake.
+// stream_destinations holds each stream's mint and the owner's token account for it, in the pool's stream order.
+pub fn reward_stream_accounts(pool: &Pubkey, stream_destinations: &[(Pubkey, Pubkey)]) -> Vec<AccountMeta> {
+    let mut accounts = Vec::with_capacity(stream_destinations.len() * REWARD_STREAM_GROUP_LEN);
+
+    for (stream_mint, owner_stream_token_account) in stream_destinations {
+        let (stream_mint_authority, _) = Pubkey::find_program_address(
+            &[MINT_AUTHORITY_PDA_SEED, pool.as_ref(), stream_mint.as_ref()],
+            &crate::ID,
+        );
+
+        accounts.push(AccountMeta::new(*stream_mint, false));
+        accounts.push(AccountMeta::new_readonly(stream_mint_authority, false));
+        accounts.push(AccountMeta::new(reward_vault_address(pool, stream_mint), false));
+        accounts.push(AccountMeta::new(*owner_stream_token_account, false));
+    }
+
+    accounts
+}
+
+// Emission schedule for set_emission_schedule that halves the reward rate every interval_seconds, starting at start_time,
+// for the given number of halvings. The rate stays at the last step after that.
+// After n halvings the multiplier is BASIS_POINTS / 2^n rounded to the nearest basis point, halves rounding up (5000, 2500, 1250, 625, 313, ...).
+// The schedule stops before a step that would round down to 0 bps, so it never stops the rewards altogether.
+pub fn halving_schedule(start_time: i64, interval_seconds: i64, halvings: u8) -> Vec<EmissionStep> {
+    (1..=halvings.min(MAX_EMISSION_STEPS as u8))
+        .map(|halving| EmissionStep {
+            start_time: start_time + interval_seconds * halving as i64,
+            multiplier_bps: ((BASIS_POINTS as u64 + (1 << (halving - 1))) >> halving) as u32,
+        })
+        .take_while(|step| step.multiplier_bps > 0)
+        .collect()
+}
+
+// Address of a pool's revenue vault (PDA) for a revenue token, with SOL_REVENUE_MINT standing for SOL
+pub fn revenue_vault_address(pool: &Pubkey, revenue_mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[REVENUE_VAULT_PDA_SEED, pool.as_ref(), revenue_mint.as_ref()], &crate::ID).0
+}
+
+// Live totals of a pool, read by get_pool_stats
+pub struct PoolStats {
+    pub staked: u64,
+    pub one_of_one_staked: u64,
+    pub flex_staked: u64,
+    // Stakes per reward curve breakpoint, as (lock_days, staked)
+    pub lock_tier_stakes: Vec<(u16, u64)>,
+    pub total_rewards_paid: u64,
+    pub unique_stakers: u64,
+}
+
+// Statistics of a pool, read from the data of its StakingPool account
+pub fn get_pool_stats(pool_data: &[u8]) -> std::result::Result<PoolStats, ProgramError> {
+    let pool = StakingPool::try_deserialize(&mut &pool_data[..])?;
+
+    Ok(PoolStats {
+        staked: pool.total_staked,
+        one_of_one_staked: pool.one_of_one_staked,
+        flex_staked: pool.flex_staked,
+        lock_tier_stakes: pool
+            .reward_curve
+            .iter()
+            .zip(pool.lock_tier_stakes.iter())
+            .map(|(point, staked)| (point.lock_days, *staked))
+            .collect(),
+        total_rewards_paid: pool.total_rewards_paid,
+        unique_stakers: pool.unique_stakers,
+    })
+}
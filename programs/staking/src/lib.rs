@@ -1,77 +1,589 @@
+// CPI contexts are built by into_*_context(&self) methods on the accounts structs, as in Anchor's examples
+#![allow(clippy::wrong_self_convention)]
+
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, CloseAccount, Mint, Token, SetAuthority, MintTo, TokenAccount, Transfer};
 use spl_token::instruction::AuthorityType;
+use solana_program::program::{invoke, invoke_signed};
+use solana_program::program_pack::Pack;
+use solana_program::system_instruction;
+use mpl_token_metadata::state::{Metadata, TokenMetadataAccount};
 use solana_program::pubkey;
+use std::convert::TryFrom;
 
 declare_id!("5QWdVhYaHiwtXLrbzRwMUmvFJuCL2MHkfza3ro3RuQnE");
 
+#[cfg(not(target_arch = "bpf"))]
+pub mod client;
+
 const MINT_AUTHORITY_PDA_SEED: &[u8] = b"authority";
 const VAULT_PDA_SEED: &[u8] = b"vault";
 const STAKING_ACCOUNT_PDA_SEED: &[u8] = b"receipt";
+const POOL_PDA_SEED: &[u8] = b"pool";
+const RARITY_PDA_SEED: &[u8] = b"rarity";
+const REWARD_VAULT_PDA_SEED: &[u8] = b"reward_vault";
+const REVENUE_VAULT_PDA_SEED: &[u8] = b"revenue_vault";
+const STAKE_PDA_SEED: &[u8] = b"stake";
+const USER_SUMMARY_PDA_SEED: &[u8] = b"user";
 const STAKING_AMOUNT: u64 = 1;
-const MINIMUM_COLLECTION_PERIOD: i64 = 86400; // 1 day in seconds
-// const MINIMUM_COLLECTION_PERIOD: i64 = 10; // testing purposes
-
-// Reward tiers for standard collection
-const ONE_WEEK_REWARD: i64 = 35;
-const TWO_WEEK_REWARD: i64 = 98;
-const FOUR_WEEK_REWARD: i64 = 280;
-
-// Reward tiers for One-of-One tokens (OOO)
-const ONE_WEEK_REWARD_OOO: i64 = 49;
-const TWO_WEEK_REWARD_OOO: i64 = 140;
-const FOUR_WEEK_REWARD_OOO: i64 = 420;
-
-// Staking period tiers, duration in seconds. Alternative shorter periods for testing purposes in comments
-const STAKING_PERIOD_ONE_WEEK: i64 = 604800;
-// const STAKING_PERIOD_ONE_WEEK: i64 = 120; // testing purposes
-const STAKING_PERIOD_TWO_WEEK: i64 = 1209600;
-// const STAKING_PERIOD_TWO_WEEK: i64 = 240; // testing purposes
-const STAKING_PERIOD_FOUR_WEEK: i64 = 2419200;
-// const STAKING_PERIOD_FOUR_WEEK: i64 = 500; // testing purposes
+
+// Maximum number of verified creators a pool can accept, matching the Metaplex creator limit
+const MAX_POOL_CREATORS: usize = 5;
+
+// Maximum number of rarity tiers a pool can define. Tier 0 applies to mints without a rarity record.
+const MAX_RARITY_TIERS: usize = 8;
+
+// Maximum number of reward streams a pool can pay on top of its reward mint
+const MAX_REWARD_STREAMS: usize = 3;
+
+// Accounts passed per reward stream to the instructions that pay them: stream_mint, stream_mint_authority, stream_vault, owner_stream_token_account
+const REWARD_STREAM_GROUP_LEN: usize = 4;
+
+// Maximum number of tokens, including SOL, whose revenue a pool can share with its stakers
+const MAX_REVENUE_TOKENS: usize = 4;
+
+// Number of pool days over which each revenue deposit is shared with the stakers
+const REVENUE_DISTRIBUTION_DAYS: i64 = 7;
+
+// Mint used to register and deposit SOL revenue
+pub const SOL_REVENUE_MINT: Pubkey = pubkey!("11111111111111111111111111111111");
+
+// Maximum number of breakpoints in a pool's lock-length reward curve
+const MAX_CURVE_POINTS: usize = 8;
+const BASIS_POINTS: i64 = 10000;
+
+// Maximum number of steps in a pool's emission schedule
+const MAX_EMISSION_STEPS: usize = 8;
+
+// Lock length used to stake without a lock, earning the pool's flex reward rate per second
+pub const FLEX_LOCK_DAYS: u16 = 0;
+
+// Accounts passed per token to stake_many: staking_mint, staking_mint_metadata, rarity_record, owner_staking_token_account, vault_account, staking_account
+const STAKE_MANY_GROUP_LEN: usize = 6;
+
+// Number of seconds between the admin requesting the release of the reward mint authority and the release being allowed.
+// Not in pool days, as the admin can change the pool's day_length.
+const MINT_AUTHORITY_RELEASE_SECONDS: i64 = 2 * 86400;
+
+// Fixed-point scale of per-second reward rates and of the fraction of a reward token carried between collections
+const REWARD_PRECISION: u128 = 1_000_000_000_000;
 
 // Size constants
 const DISCRIMINATOR_LENGTH: usize = 8;
 const PUBLIC_KEY_LENGTH: usize = 32;
 const TIMESTAMP_LENGTH: usize = 8;
 
-// Mint Information
-// const MINT_AUTHORITY_PUBLIC_KEY: Pubkey = pubkey!("3iJqzWcBEmjrvDKuWMAzgKnfqnWbqcaQc9kvYbHJg1gf"); // No longer necessary
-const MINT_ADDRESS: Pubkey = pubkey!("MAGf4MnUUkkAUUdiYbNFcDnE4EBGHJYLk9foJ2ae7BV");
-
-// Admin for PDA initialization - Below address is for example only
+// Admin for pool initialization - Below address is for example only
 const AUTHORITY_INIT: Pubkey = pubkey!("EuMw7xW3yW3ZsiVEdRZjtJhqNNA8ALXwqCCsAuAUNYjR");
 
 #[program]
 pub mod staking {
     use super::*;
-    // Create PDA that will be used as the Mint Authority for the Reward Token.
+    // Create the pool account for a collection, holding the reward mint, admin, lock limits, reward curve and rarity tiers.
+    // base_reward_per_day is in base units of the reward token, and emission_per_day in whole tokens.
+    #[allow(clippy::too_many_arguments)]
+    pub fn initialize_pool(
+        ctx: Context<InitializePool>,
+        collection: Pubkey,
+        creators: Vec<Pubkey>,
+        min_lock_days: u16,
+        max_lock_days: u16,
+        base_reward_per_day: i64,
+        reward_curve: Vec<CurvePoint>,
+        rarity_tiers: Vec<RarityTier>,
+        minimum_collection_period: i64,
+        day_length: i64,
+        mode: PoolMode,
+        emission_per_day: u64,
+    ) -> ProgramResult {
+
+        // Check that the lock limits leave room for flex staking at zero days
+        if day_length <= 0
+            || min_lock_days == FLEX_LOCK_DAYS
+            || min_lock_days > max_lock_days
+            || creators.len() > MAX_POOL_CREATORS
+        {
+            return Err(ErrorCode::InvalidPoolConfig.into())
+        }
+
+        check_reward_config(base_reward_per_day, &reward_curve, &rarity_tiers, minimum_collection_period)?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.collection = collection;
+        pool.creators = creators;
+        pool.admin = *ctx.accounts.admin.key;
+        pool.reward_mint = *ctx.accounts.reward_mint.to_account_info().key;
+        pool.min_lock_days = min_lock_days;
+        pool.max_lock_days = max_lock_days;
+        pool.base_reward_per_day = base_reward_per_day;
+        pool.reward_decimals = ctx.accounts.reward_mint.decimals;
+        pool.reward_curve = reward_curve;
+        pool.rarity_tiers = rarity_tiers;
+        pool.minimum_collection_period = minimum_collection_period;
+        pool.day_length = day_length;
+
+        // Shared emission pools split emission_per_day between all staked weight instead of paying each stake a fixed reward
+        pool.mode = mode;
+        pool.emission_per_day = emission_per_day;
+        pool.acc_reward_per_share = 0;
+        pool.total_staked_weight = 0;
+        pool.last_emission_update = Clock::get()?.unix_timestamp;
+
+        // Rewards are minted through the mint authority PDA until the admin creates a reward vault
+        pool.reward_source = RewardSource::Mint;
+        pool.pending_mint_authority = None;
+        pool.mint_authority_release_time = 0;
+
+        // Pools only pay their reward mint until the admin adds reward streams
+        pool.reward_streams = Vec::new();
+
+        // Revenue is shared between staked tokens once the admin registers the tokens it is paid in
+        pool.revenue_tokens = Vec::new();
+        pool.total_staked = 0;
+
+        // Pool statistics, with one stake count per breakpoint of the reward curve
+        pool.one_of_one_staked = 0;
+        pool.flex_staked = 0;
+        pool.lock_tier_stakes = vec![0; pool.reward_curve.len()];
+        pool.total_rewards_paid = 0;
+        pool.unique_stakers = 0;
+
+        // Rewards are paid at the full rate until the admin sets an emission schedule
+        pool.emission_schedule = Vec::new();
+
+        // Rewards are not limited until the admin sets an emission budget
+        pool.emission_cap = 0;
+        pool.epoch_budget = 0;
+        pool.epoch_length = 0;
+        pool.epoch_start = 0;
+        pool.epoch_paid = 0;
+
+        // Pools start without an early unstake penalty, with penalties paid to the admin until a treasury is set
+        pool.treasury = *ctx.accounts.admin.key;
+        pool.early_unstake_penalty_kind = PenaltyKind::None;
+        pool.early_unstake_base_penalty = 0;
+        pool.early_unstake_penalty_per_day = 0;
+
+        // Flex staking is disabled until the admin sets a flex reward rate
+        pool.flex_reward_rate = 0;
+        pool.flex_cooldown = 0;
+
+        Ok(())
+    }
+    // Change the reward rate, reward curve, rarity tiers and minimum collection period of a pool. Rewards not collected yet are paid at
+    // the new rates. Stakes and rarity records refer to rarity tiers by index, so tiers can only be added, and while tokens are staked
+    // the curve's breakpoints must keep their lock lengths.
+    pub fn update_pool(
+        ctx: Context<UpdatePool>,
+        base_reward_per_day: i64,
+        reward_curve: Vec<CurvePoint>,
+        rarity_tiers: Vec<RarityTier>,
+        minimum_collection_period: i64,
+    ) -> ProgramResult {
+
+        check_reward_config(base_reward_per_day, &reward_curve, &rarity_tiers, minimum_collection_period)?;
+
+        let pool = &mut ctx.accounts.pool;
+
+        let curve_moved = reward_curve.len() != pool.reward_curve.len()
+            || reward_curve.iter().zip(pool.reward_curve.iter()).any(|(new, old)| new.lock_days != old.lock_days);
+
+        if rarity_tiers.len() < pool.rarity_tiers.len() || (curve_moved && pool.total_staked > 0) {
+            return Err(ErrorCode::InvalidPoolConfig.into())
+        }
+
+        // Nothing is staked if the breakpoints moved, so the stake counts per breakpoint start again from zero
+        if curve_moved {
+            pool.lock_tier_stakes = vec![0; reward_curve.len()];
+        }
+
+        pool.base_reward_per_day = base_reward_per_day;
+        pool.reward_curve = reward_curve;
+        pool.rarity_tiers = rarity_tiers;
+        pool.minimum_collection_period = minimum_collection_period;
+
+        Ok(())
+    }
+    // Create PDA that will be used as the Mint Authority for the pool's Reward Token.
     pub fn init_mint_authority(_ctx: Context<AuthorityInit>) -> ProgramResult {
         Ok(())
     }
-    // Allow user to stake a single NFT
-    pub fn stake(ctx: Context<Stake>, staking_period: u16, is_one_of_one: bool) -> ProgramResult {
+    // Create the pool's reward vault (PDA), owned by the reward mint authority (PDA), and pay rewards from it instead of minting them.
+    // For reward tokens with a fixed supply, where the program cannot hold the mint authority.
+    pub fn init_reward_vault(ctx: Context<RewardVaultInit>) -> ProgramResult {
+        ctx.accounts.pool.reward_source = RewardSource::Vault;
 
-        // Check that the staking period is valid
-        if staking_period > 2 {
-            return Err(ErrorCode::InvalidStakingPeriod.into())
+        Ok(())
+    }
+    // Start the timelock for handing the reward mint authority from the PDA to new_authority. The release can be executed
+    // with release_mint_authority once MINT_AUTHORITY_RELEASE_SECONDS have passed. A new request restarts the timelock.
+    pub fn request_mint_authority_release(ctx: Context<RequestMintAuthorityRelease>, new_authority: Pubkey) -> ProgramResult {
+
+        let timestamp = Clock::get()?.unix_timestamp;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.pending_mint_authority = Some(new_authority);
+        pool.mint_authority_release_time = timestamp + MINT_AUTHORITY_RELEASE_SECONDS;
+
+        Ok(())
+    }
+    // Cancel a pending release of the reward mint authority
+    pub fn cancel_mint_authority_release(ctx: Context<CancelMintAuthorityRelease>) -> ProgramResult {
+
+        if ctx.accounts.pool.pending_mint_authority.is_none() {
+            return Err(ErrorCode::MintAuthorityReleaseNotRequested.into())
         }
 
-        // Define variables based on args/time
+        let pool = &mut ctx.accounts.pool;
+        pool.pending_mint_authority = None;
+        pool.mint_authority_release_time = 0;
+
+        Ok(())
+    }
+    // Hand the reward mint authority from the PDA to the key chosen in request_mint_authority_release, once the timelock has passed.
+    // Pools that mint their rewards can no longer pay them afterwards, so this is for sunsetting or migrating the pool.
+    pub fn release_mint_authority(ctx: Context<ReleaseMintAuthority>) -> ProgramResult {
+
+        let timestamp = Clock::get()?.unix_timestamp;
+
+        // Check that a release has been requested, and that the timelock has passed
+        let new_authority = match ctx.accounts.pool.pending_mint_authority {
+            Some(new_authority) => new_authority,
+            None => return Err(ErrorCode::MintAuthorityReleaseNotRequested.into()),
+        };
+
+        if timestamp < ctx.accounts.pool.mint_authority_release_time {
+            return Err(ErrorCode::MintAuthorityReleaseLocked.into())
+        }
+
+        // Find the PDA/bump and set the signature
+        let (_mint_authority, mint_authority_bump) =
+            Pubkey::find_program_address(&[MINT_AUTHORITY_PDA_SEED, ctx.accounts.pool.to_account_info().key.as_ref(), ctx.accounts.reward_mint.to_account_info().key.as_ref()], ctx.program_id);
+
+        let seeds = &[MINT_AUTHORITY_PDA_SEED, ctx.accounts.pool.to_account_info().key.as_ref(), ctx.accounts.reward_mint.to_account_info().key.as_ref(), &[mint_authority_bump]];
+        let authority_seeds = [&seeds[..]];
+
+        token::set_authority(
+            ctx.accounts.into_set_authority_context().with_signer(&authority_seeds),
+            AuthorityType::MintTokens,
+            Some(new_authority),
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.pending_mint_authority = None;
+        pool.mint_authority_release_time = 0;
+
+        Ok(())
+    }
+    // Add a reward stream paying reward_per_day whole tokens of stream_mint per day on top of the pool's reward mint, scaled by the stake's
+    // rarity tier and lock-length multipliers. The stream's vault (PDA) is created with it, and is only used if the stream pays from its vault.
+    pub fn add_reward_stream(ctx: Context<AddRewardStream>, reward_per_day: u64, source: RewardSource) -> ProgramResult {
+
+        let stream_mint = *ctx.accounts.stream_mint.to_account_info().key;
+
+        // Check that there is room for the stream, and that the mint is not paid by the pool already
+        if ctx.accounts.pool.reward_streams.len() >= MAX_REWARD_STREAMS
+            || stream_mint == ctx.accounts.pool.reward_mint
+            || ctx.accounts.pool.reward_streams.iter().any(|stream| stream.mint == stream_mint)
+        {
+            return Err(ErrorCode::InvalidPoolConfig.into())
+        }
+
+        ctx.accounts.pool.reward_streams.push(RewardStream {
+            mint: stream_mint,
+            reward_per_day,
+            decimals: ctx.accounts.stream_mint.decimals,
+            source,
+            start_time: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+    // Register a token whose revenue is shared with the pool's stakers, with SOL_REVENUE_MINT standing for SOL, and create its revenue vault (PDA).
+    // The SOL vault is a system account, funded by the admin with its rent-exempt balance. Token vaults are owned by the mint's authority (PDA).
+    pub fn add_revenue_token(ctx: Context<AddRevenueToken>) -> ProgramResult {
+
+        let revenue_mint = *ctx.accounts.revenue_mint.key;
+
+        // Check that there is room for the token, and that it is not registered already
+        if ctx.accounts.pool.revenue_tokens.len() >= MAX_REVENUE_TOKENS
+            || ctx.accounts.pool.revenue_tokens.iter().any(|token| token.mint == revenue_mint)
+        {
+            return Err(ErrorCode::InvalidPoolConfig.into())
+        }
+
+        if revenue_mint == SOL_REVENUE_MINT {
+            invoke(
+                &system_instruction::transfer(ctx.accounts.admin.key, ctx.accounts.revenue_vault.key, ctx.accounts.rent.minimum_balance(0)),
+                &[
+                    ctx.accounts.admin.to_account_info(),
+                    ctx.accounts.revenue_vault.clone(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+        } else {
+            Account::<Mint>::try_from(&ctx.accounts.revenue_mint)?;
+
+            // Create the revenue vault (PDA), owned by the mint's authority (PDA)
+            let (_revenue_vault, revenue_vault_bump) =
+                Pubkey::find_program_address(&[REVENUE_VAULT_PDA_SEED, ctx.accounts.pool.to_account_info().key.as_ref(), revenue_mint.as_ref()], ctx.program_id);
+
+            let vault_seeds = &[REVENUE_VAULT_PDA_SEED, ctx.accounts.pool.to_account_info().key.as_ref(), revenue_mint.as_ref(), &[revenue_vault_bump]];
+
+            create_pda_account(
+                &ctx.accounts.admin.to_account_info(),
+                &ctx.accounts.revenue_vault,
+                &ctx.accounts.system_program.to_account_info(),
+                &ctx.accounts.rent,
+                spl_token::state::Account::LEN,
+                &spl_token::ID,
+                &vault_seeds[..],
+            )?;
+
+            invoke(
+                &spl_token::instruction::initialize_account2(&spl_token::ID, ctx.accounts.revenue_vault.key, &revenue_mint, ctx.accounts.revenue_authority.key)?,
+                &[
+                    ctx.accounts.revenue_vault.clone(),
+                    ctx.accounts.revenue_mint.clone(),
+                    ctx.accounts.rent.to_account_info(),
+                    ctx.accounts.token_program.to_account_info(),
+                ],
+            )?;
+        }
+
+        let timestamp = Clock::get()?.unix_timestamp;
+
+        ctx.accounts.pool.revenue_tokens.push(RevenueToken {
+            mint: revenue_mint,
+            acc_revenue_per_token: 0,
+            revenue_rate: 0,
+            distribution_end: timestamp,
+            last_update: timestamp,
+            total_deposited: 0,
+        });
+
+        Ok(())
+    }
+    // Deposit revenue in one of the pool's revenue tokens, or lamports for SOL. Anyone can deposit revenue.
+    // The deposit is shared between the staked tokens over the next REVENUE_DISTRIBUTION_DAYS pool days, along with what is left of earlier deposits,
+    // so each staked token's share follows how long it is staked.
+    pub fn deposit_revenue(ctx: Context<DepositRevenue>, amount: u64) -> ProgramResult {
+
+        if amount == 0 {
+            return Err(ErrorCode::InvalidFundingAmount.into())
+        }
+
+        let timestamp = Clock::get()?.unix_timestamp;
+        let revenue_mint = *ctx.accounts.revenue_mint.key;
+
+        if revenue_mint == SOL_REVENUE_MINT {
+            invoke(
+                &system_instruction::transfer(ctx.accounts.depositor.key, ctx.accounts.revenue_vault.key, amount),
+                &[
+                    ctx.accounts.depositor.to_account_info(),
+                    ctx.accounts.revenue_vault.clone(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+        } else {
+            token::transfer(ctx.accounts.into_transfer_to_vault_context(), amount)?;
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        let duration = REVENUE_DISTRIBUTION_DAYS * pool.day_length;
+        let total_staked = pool.total_staked;
+
+        let revenue_token = pool.revenue_tokens
+            .iter_mut()
+            .find(|token| token.mint == revenue_mint)
+            .ok_or(ErrorCode::InvalidRevenueToken)?;
+
+        revenue_token.update(total_staked, timestamp)?;
+        revenue_token.distribute((amount as u128).checked_mul(REWARD_PRECISION).ok_or(ErrorCode::MathOverflow)?, duration, timestamp)?;
+        revenue_token.total_deposited = revenue_token.total_deposited.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(())
+    }
+    // Pay a staked token's share of the revenue in one of the pool's revenue tokens, in whole base units or lamports,
+    // with the fraction left over carried to the next claim. SOL revenue is paid to the owner's wallet.
+    // Nothing is done while less than a whole unit is pending, so that a claim can always be sent along with an unstake.
+    pub fn claim_revenue(ctx: Context<ClaimRevenue>) -> ProgramResult {
+
+        let timestamp = Clock::get()?.unix_timestamp;
+        let revenue_mint = *ctx.accounts.revenue_mint.key;
+        let total_staked = ctx.accounts.pool.total_staked;
+
+        let index = ctx.accounts.pool.revenue_tokens
+            .iter()
+            .position(|token| token.mint == revenue_mint)
+            .ok_or(ErrorCode::InvalidRevenueToken)?;
+
+        // Bring the revenue shared so far up to date, and find the token's share of it
+        let revenue_token = &mut ctx.accounts.pool.revenue_tokens[index];
+        revenue_token.update(total_staked, timestamp)?;
+        let acc_revenue_per_token = revenue_token.acc_revenue_per_token;
+
+        let revenue_share = ctx.accounts.staking_account.revenue_share(index);
+        let pending = acc_revenue_per_token.checked_sub(revenue_share.debt).ok_or(ErrorCode::MathOverflow)?;
+        let amount = u64::try_from(pending / REWARD_PRECISION).map_err(|_| ErrorCode::MathOverflow)?;
+
+        if amount == 0 {
+            return Ok(())
+        }
+
+        revenue_share.debt = acc_revenue_per_token - pending % REWARD_PRECISION;
+        revenue_share.claimed = revenue_share.claimed.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+
+        let pool_key = ctx.accounts.pool.key();
+
+        if revenue_mint == SOL_REVENUE_MINT {
+            // Find the PDA/bump of the SOL vault and set the signature
+            let (_revenue_vault, revenue_vault_bump) =
+                Pubkey::find_program_address(&[REVENUE_VAULT_PDA_SEED, pool_key.as_ref(), revenue_mint.as_ref()], ctx.program_id);
+
+            let vault_seeds = &[REVENUE_VAULT_PDA_SEED, pool_key.as_ref(), revenue_mint.as_ref(), &[revenue_vault_bump]];
+
+            invoke_signed(
+                &system_instruction::transfer(ctx.accounts.revenue_vault.key, ctx.accounts.staking_token_owner.key, amount),
+                &[
+                    ctx.accounts.revenue_vault.clone(),
+                    ctx.accounts.staking_token_owner.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+                &[&vault_seeds[..]],
+            )?;
+        } else {
+            // Find the PDA/bump of the vault's authority and set the signature
+            let (_revenue_authority, revenue_authority_bump) =
+                Pubkey::find_program_address(&[MINT_AUTHORITY_PDA_SEED, pool_key.as_ref(), revenue_mint.as_ref()], ctx.program_id);
+
+            let seeds = &[MINT_AUTHORITY_PDA_SEED, pool_key.as_ref(), revenue_mint.as_ref(), &[revenue_authority_bump]];
+            let authority_seeds = [&seeds[..]];
+
+            token::transfer(ctx.accounts.into_transfer_to_owner_context().with_signer(&authority_seeds), amount)?;
+        }
+
+        Ok(())
+    }
+    // Deposit reward tokens into the pool's vault for the token's mint: the reward vault, or the vault of one of the pool's reward streams.
+    // Anyone can fund a pool.
+    pub fn fund_pool(ctx: Context<FundPool>, amount: u64) -> ProgramResult {
+
+        if amount == 0 {
+            return Err(ErrorCode::InvalidFundingAmount.into())
+        }
+
+        token::transfer(ctx.accounts.into_transfer_to_vault_context(), amount)?;
+
+        Ok(())
+    }
+    // Assign a mint to one of the pool's rarity tiers, creating its rarity record if needed
+    pub fn set_rarity(ctx: Context<SetRarity>, rarity_tier: u8) -> ProgramResult {
+
+        // Check that the rarity tier exists on the pool
+        if rarity_tier as usize >= ctx.accounts.pool.rarity_tiers.len() {
+            return Err(ErrorCode::InvalidRarityTier.into())
+        }
+
+        ctx.accounts.rarity_record.pool = ctx.accounts.pool.key();
+        ctx.accounts.rarity_record.mint = *ctx.accounts.mint.to_account_info().key;
+        ctx.accounts.rarity_record.rarity_tier = rarity_tier;
+
+        Ok(())
+    }
+    // Remove a mint's rarity record, returning it to the standard tier. Tokens that are already staked keep the tier they were staked with.
+    pub fn remove_rarity(_ctx: Context<RemoveRarity>) -> ProgramResult {
+        Ok(())
+    }
+    // Set the pool's early unstake penalty schedule: a base amount plus an amount for each day of lock left (rounded up),
+    // paid in reward tokens or lamports to the treasury
+    pub fn set_early_unstake_penalty(ctx: Context<SetEarlyUnstakePenalty>, penalty_kind: PenaltyKind, base_penalty: u64, penalty_per_day: u64) -> ProgramResult {
+
+        let pool = &mut ctx.accounts.pool;
+        pool.treasury = *ctx.accounts.treasury.key;
+        pool.early_unstake_penalty_kind = penalty_kind;
+        pool.early_unstake_base_penalty = base_penalty;
+        pool.early_unstake_penalty_per_day = penalty_per_day;
+
+        Ok(())
+    }
+    // Enable flex staking on the pool, with no lock and rewards accruing per second. flex_reward_rate is paid per day in base units of the reward token,
+    // and a flex_reward_rate of zero disables it.
+    // Flex stakes can be unstaked once flex_cooldown seconds have passed since staking.
+    pub fn set_flex_mode(ctx: Context<SetFlexMode>, flex_reward_rate: i64, flex_cooldown: i64) -> ProgramResult {
+
+        if flex_reward_rate < 0 || flex_cooldown < 0 {
+            return Err(ErrorCode::InvalidPoolConfig.into())
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        pool.flex_reward_rate = flex_reward_rate;
+        pool.flex_cooldown = flex_cooldown;
+
+        Ok(())
+    }
+    // Limit the reward tokens the pool pays from its reward mint, in base units: emission_cap over the pool's lifetime, and epoch_budget
+    // in each epoch of epoch_days days, starting now. A cap or budget of zero means no limit. Reward streams are not limited.
+    pub fn set_emission_budget(ctx: Context<SetEmissionBudget>, emission_cap: u64, epoch_budget: u64, epoch_days: u16) -> ProgramResult {
+
+        if epoch_budget > 0 && epoch_days == 0 {
+            return Err(ErrorCode::InvalidPoolConfig.into())
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        pool.emission_cap = emission_cap;
+        pool.epoch_budget = epoch_budget;
+        pool.epoch_length = epoch_days as i64 * pool.day_length;
+        pool.epoch_start = Clock::get()?.unix_timestamp;
+        pool.epoch_paid = 0;
+
+        Ok(())
+    }
+    // Scale the pool's reward rate over time, for example to halve it at regular intervals. Each step applies its multiplier_bps from its start_time
+    // until the next step, and the full rate applies before the first step. Steps that have already started cannot be changed.
+    pub fn set_emission_schedule(ctx: Context<SetEmissionSchedule>, emission_schedule: Vec<EmissionStep>) -> ProgramResult {
+
         let clock: Clock = Clock::get().unwrap();
         let timestamp = clock.unix_timestamp;
 
-        let mut unstake: i64 = 0;
+        // Check that the steps are in increasing order of start time
+        if emission_schedule.len() > MAX_EMISSION_STEPS
+            || emission_schedule.windows(2).any(|steps| steps[0].start_time >= steps[1].start_time)
+        {
+            return Err(ErrorCode::InvalidPoolConfig.into())
+        }
+
+        // Check that the steps that have already started are kept as they are, so that rewards already earned do not change
+        let started = ctx.accounts.pool.emission_schedule.iter().filter(|step| step.start_time <= timestamp).count();
 
-        if staking_period == 0 {
-            unstake = STAKING_PERIOD_ONE_WEEK;
-        } else if staking_period == 1 {
-            unstake = STAKING_PERIOD_TWO_WEEK;
-        } else if staking_period == 2 {
-            unstake = STAKING_PERIOD_FOUR_WEEK;
+        if emission_schedule.len() < started
+            || emission_schedule[..started] != ctx.accounts.pool.emission_schedule[..started]
+            || emission_schedule[started..].iter().any(|step| step.start_time <= timestamp)
+        {
+            return Err(ErrorCode::InvalidPoolConfig.into())
         }
 
+        // In shared emission pools, bring the pool's emissions up to date at the current schedule first
+        ctx.accounts.pool.update_emission(timestamp)?;
+        ctx.accounts.pool.emission_schedule = emission_schedule;
+
+        Ok(())
+    }
+    // Allow user to stake a single NFT, locked for lock_days days
+    pub fn stake(ctx: Context<Stake>, lock_days: u16) -> ProgramResult {
+
+        // Check that the lock length is valid, and find how long the token is locked for
+        let unstake: i64 = ctx.accounts.pool.lock_duration(lock_days)?;
+
+        // Check that the token belongs to the pool's collection, either through a verified collection or a verified creator
+        ctx.accounts.pool.check_collection_member(ctx.accounts.staking_mint.to_account_info().key, &ctx.accounts.staking_mint_metadata)?;
+
+        // Define variables based on args/time
+        let clock: Clock = Clock::get().unwrap();
+        let timestamp = clock.unix_timestamp;
+
         // Define properties of staking_account account that will be created as a record of the staked token
+        ctx.accounts.staking_account.pool = ctx.accounts.pool.key();
         ctx.accounts.staking_account.staking_token_owner = *ctx.accounts.staking_token_owner.key;
         ctx.accounts
             .staking_account
@@ -82,24 +594,40 @@ pub mod staking {
             .key;
         ctx.accounts.staking_account.staking_mint = *ctx.accounts.staking_mint.to_account_info().key;
 
-        if is_one_of_one == true {
-            ctx.accounts.staking_account.is_one_of_one = true;
-        } else {
-            ctx.accounts.staking_account.is_one_of_one = false;
-        }
+        // The token's rarity tier comes from the rarity record set by the admin, or the standard tier if there is none
+        let rarity_tier = ctx.accounts.pool.rarity_tier(ctx.program_id, &ctx.accounts.rarity_record)?;
+        ctx.accounts.staking_account.rarity_tier = rarity_tier;
+        ctx.accounts.staking_account.is_one_of_one = ctx.accounts.pool.rarity_tiers[rarity_tier as usize].is_one_of_one;
 
         ctx.accounts.staking_account.created = timestamp;
 
         ctx.accounts.staking_account.owner_reward_token_account = *ctx.accounts.owner_reward_token_account.to_account_info().key;
         ctx.accounts.staking_account.last_reward_collection = timestamp;
         ctx.accounts.staking_account.total_reward_collected = 0;
+        ctx.accounts.staking_account.auto_renew = false;
+        ctx.accounts.staking_account.reward_remainder = 0;
+        ctx.accounts.staking_account.stream_rewards = Vec::new();
+        ctx.accounts.staking_account.revenue_shares = Vec::new();
 
-        ctx.accounts.staking_account.staking_period = staking_period;
+        ctx.accounts.staking_account.lock_days = lock_days;
         ctx.accounts.staking_account.unstake_date = timestamp + unstake;
 
+        // In shared emission pools, bring the pool's emissions up to date before the token starts sharing them
+        ctx.accounts.pool.update_emission(timestamp)?;
+        ctx.accounts.pool.add_stake_weight(&mut ctx.accounts.staking_account)?;
+
+        // Start the token sharing the pool's revenue
+        ctx.accounts.pool.add_staked_token(&mut ctx.accounts.staking_account, timestamp)?;
+
+        // Count the stake in the owner's summary for the pool, and the owner as a new staker on their first stake
+        if ctx.accounts.user_stake_summary.first_stake_time == 0 {
+            ctx.accounts.pool.add_unique_staker()?;
+        }
+        ctx.accounts.user_stake_summary.add_stake(ctx.accounts.pool.key(), *ctx.accounts.staking_token_owner.key, timestamp)?;
+
         // Set authority for staking vault (PDA)
         let (vault_authority, _vault_authority_bump) =
-            Pubkey::find_program_address(&[VAULT_PDA_SEED, &*ctx.accounts.staking_account.to_account_info().key.as_ref(), &*ctx.accounts.staking_mint.to_account_info().key.as_ref()], ctx.program_id);
+            Pubkey::find_program_address(&[VAULT_PDA_SEED, ctx.accounts.staking_account.to_account_info().key.as_ref(), ctx.accounts.staking_mint.to_account_info().key.as_ref()], ctx.program_id);
 
         token::set_authority(
             ctx.accounts.into_set_authority_context(),
@@ -116,454 +644,2609 @@ pub mod staking {
         Ok(())
     }
 
-    // Allow for collection of rewards over the course of staking period. 
-    // Must allow at least one day to pass in between collection attempts
-    pub fn collect(ctx: Context<Collect>) -> ProgramResult {
+    // Allow user to stake several NFTs for the same lock length in one transaction.
+    // Each token is passed through remaining_accounts as a group of STAKE_MANY_GROUP_LEN accounts, with the staking_account derived from the pool and staking_mint.
+    pub fn stake_many<'info>(ctx: Context<'_, '_, '_, 'info, StakeMany<'info>>, lock_days: u16) -> ProgramResult {
 
-        // Define time-related variables
+        // Check that the lock length is valid, and find how long the tokens are locked for
+        let unstake: i64 = ctx.accounts.pool.lock_duration(lock_days)?;
+
+        // Check that at least one complete group of accounts has been passed
+        if ctx.remaining_accounts.is_empty() || !ctx.remaining_accounts.chunks_exact(STAKE_MANY_GROUP_LEN).remainder().is_empty() {
+            return Err(ErrorCode::InvalidStakeManyAccounts.into())
+        }
+
+        // Define variables based on args/time
         let clock: Clock = Clock::get().unwrap();
         let timestamp = clock.unix_timestamp;
-        let elapsed: i64 = timestamp - ctx.accounts.staking_account.last_reward_collection;
 
-        // Check that minimum collection time has elapsed
-        if elapsed < MINIMUM_COLLECTION_PERIOD {
-            return Err(ErrorCode::NotEnoughElapsedSinceLastCollection.into())
-        }
+        let pool_key = ctx.accounts.pool.key();
 
-        // Check that the reward has not already been fully collected - SATISFY SOTERIA ISSUE M-2
-        if ctx.accounts.staking_account.full_reward_collected == true {
-             return Err(ErrorCode::FullRewardAlreadyCollected.into())
-        }
+        // In shared emission pools, bring the pool's emissions up to date before the tokens start sharing them
+        ctx.accounts.pool.update_emission(timestamp)?;
 
-        // Check that the staking period is valid
-        if ctx.accounts.staking_account.staking_period > 2 {
-            return Err(ErrorCode::InvalidStakingPeriod.into())
-        }
+        for group in ctx.remaining_accounts.chunks(STAKE_MANY_GROUP_LEN) {
+            let staking_mint = &group[0];
+            let staking_mint_metadata = &group[1];
+            let rarity_record = &group[2];
+            let owner_staking_token_account = &group[3];
+            let vault_account = &group[4];
+            let staking_account = &group[5];
 
-        // Establish number of full days that have passed since staking/last collection
-        let days = elapsed / MINIMUM_COLLECTION_PERIOD;
-        let mut amount: i64 = 0;
-        let mut full_amount: i64 = 0;
-
-        // Define the "per diem" rate of the staking period and multiply by "days" to determine amount to be rewarded. 
-        // Define the full_amount based on the staking period.
-        if ctx.accounts.staking_account.is_one_of_one == true {
-            if ctx.accounts.staking_account.staking_period == 0 {
-                amount = (ONE_WEEK_REWARD_OOO as i64 / 7) * days as i64;
-                full_amount = ONE_WEEK_REWARD_OOO
-            } else if ctx.accounts.staking_account.staking_period == 1 {
-                amount = (TWO_WEEK_REWARD_OOO as i64 / 14) * days as i64;
-                full_amount = TWO_WEEK_REWARD_OOO
-            } else if ctx.accounts.staking_account.staking_period == 2 {
-                amount = (FOUR_WEEK_REWARD_OOO as i64 / 28) * days as i64;
-                full_amount = FOUR_WEEK_REWARD_OOO
+            // Check that the owner holds the token, and that it belongs to the pool's collection
+            Account::<Mint>::try_from(staking_mint)?;
+            let token_account = Account::<TokenAccount>::try_from(owner_staking_token_account)?;
+            if token_account.amount != STAKING_AMOUNT || token_account.mint != *staking_mint.key {
+                return Err(ErrorCode::InvalidStakeManyAccounts.into())
             }
-        } else {
-            if ctx.accounts.staking_account.staking_period == 0 {
-                amount = (ONE_WEEK_REWARD as i64 / 7) * days as i64;
-                full_amount = ONE_WEEK_REWARD
-            } else if ctx.accounts.staking_account.staking_period == 1 {
-                amount = (TWO_WEEK_REWARD as i64 / 14) * days as i64;
-                full_amount = TWO_WEEK_REWARD
-            } else if ctx.accounts.staking_account.staking_period == 2 {
-                amount = (FOUR_WEEK_REWARD as i64 / 28) * days as i64;
-                full_amount = FOUR_WEEK_REWARD
+
+            ctx.accounts.pool.check_collection_member(staking_mint.key, staking_mint_metadata)?;
+
+            // Check the derived addresses of the staking_account, rarity record and vault
+            let (staking_account_address, staking_account_bump) =
+                Pubkey::find_program_address(&[STAKE_PDA_SEED, pool_key.as_ref(), staking_mint.key.as_ref()], ctx.program_id);
+            let (rarity_record_address, _rarity_record_bump) =
+                Pubkey::find_program_address(&[RARITY_PDA_SEED, pool_key.as_ref(), staking_mint.key.as_ref()], ctx.program_id);
+            let (vault_account_address, vault_account_bump) =
+                Pubkey::find_program_address(&[STAKING_ACCOUNT_PDA_SEED, staking_account.key.as_ref(), staking_mint.key.as_ref()], ctx.program_id);
+            let (vault_authority, _vault_authority_bump) =
+                Pubkey::find_program_address(&[VAULT_PDA_SEED, staking_account.key.as_ref(), staking_mint.key.as_ref()], ctx.program_id);
+
+            if *staking_account.key != staking_account_address || *rarity_record.key != rarity_record_address || *vault_account.key != vault_account_address {
+                return Err(ErrorCode::InvalidStakeManyAccounts.into())
+            }
+
+            let rarity_tier = ctx.accounts.pool.rarity_tier(ctx.program_id, rarity_record)?;
+
+            // Create the staking_account (PDA) that will be kept as a record of the staked token
+            let staking_account_seeds = &[STAKE_PDA_SEED, pool_key.as_ref(), staking_mint.key.as_ref(), &[staking_account_bump]];
+
+            create_pda_account(
+                &ctx.accounts.staking_token_owner.to_account_info(),
+                staking_account,
+                &ctx.accounts.system_program.to_account_info(),
+                &ctx.accounts.rent,
+                StakeAccount::LEN,
+                ctx.program_id,
+                &staking_account_seeds[..],
+            )?;
+
+            let mut record = StakeAccount {
+                pool: pool_key,
+                staking_token_owner: *ctx.accounts.staking_token_owner.key,
+                owner_staking_token_account: *owner_staking_token_account.key,
+                staking_mint: *staking_mint.key,
+                created: timestamp,
+                unstake_date: timestamp + unstake,
+                lock_days,
+                is_one_of_one: ctx.accounts.pool.rarity_tiers[rarity_tier as usize].is_one_of_one,
+                rarity_tier,
+                full_reward_collected: false,
+                last_reward_collection: timestamp,
+                total_reward_collected: 0,
+                owner_reward_token_account: *ctx.accounts.owner_reward_token_account.to_account_info().key,
+                auto_renew: false,
+                reward_remainder: 0,
+                weight: 0,
+                reward_debt: 0,
+                stream_rewards: Vec::new(),
+                revenue_shares: Vec::new(),
+            };
+            ctx.accounts.pool.add_stake_weight(&mut record)?;
+            ctx.accounts.pool.add_staked_token(&mut record, timestamp)?;
+            if ctx.accounts.user_stake_summary.first_stake_time == 0 {
+                ctx.accounts.pool.add_unique_staker()?;
             }
+            ctx.accounts.user_stake_summary.add_stake(pool_key, *ctx.accounts.staking_token_owner.key, timestamp)?;
+            record.try_serialize(&mut &mut staking_account.try_borrow_mut_data()?[..])?;
+
+            // Create the staking vault (PDA), owned by the vault authority (PDA)
+            let vault_seeds = &[STAKING_ACCOUNT_PDA_SEED, staking_account.key.as_ref(), staking_mint.key.as_ref(), &[vault_account_bump]];
+
+            create_pda_account(
+                &ctx.accounts.staking_token_owner.to_account_info(),
+                vault_account,
+                &ctx.accounts.system_program.to_account_info(),
+                &ctx.accounts.rent,
+                spl_token::state::Account::LEN,
+                &spl_token::ID,
+                &vault_seeds[..],
+            )?;
+
+            invoke(
+                &spl_token::instruction::initialize_account2(&spl_token::ID, vault_account.key, staking_mint.key, &vault_authority)?,
+                &[
+                    vault_account.clone(),
+                    staking_mint.clone(),
+                    ctx.accounts.rent.to_account_info(),
+                    ctx.accounts.token_program.to_account_info(),
+                ],
+            )?;
+
+            // Transfer token to PDA
+            token::transfer(
+                ctx.accounts.into_transfer_to_pda_context(owner_staking_token_account.clone(), vault_account.clone()),
+                STAKING_AMOUNT,
+            )?;
         }
 
-        // Check that the reward has not already been fully collected
-        if ctx.accounts.staking_account.total_reward_collected >= full_amount{
-             return Err(ErrorCode::FullRewardAlreadyCollected.into())
+        Ok(())
+    }
+
+    // Allow user to move a staked token to a longer lock without unstaking it.
+    // The new lock counts from when the token was staked, and rewards already collected are kept towards the new full reward
+    pub fn extend_stake(ctx: Context<ExtendStake>, lock_days: u16) -> ProgramResult {
+
+        // Define time-related variables
+        let clock: Clock = Clock::get().unwrap();
+        let timestamp = clock.unix_timestamp;
+
+        // Check that the stake is still live
+        if ctx.accounts.staking_account.unstake_date <= timestamp {
+            return Err(ErrorCode::StakingPeriodEnded.into())
         }
 
-        // Catch cases that might results in the staking_token_owner collecting more than the full_amount
-        if amount > full_amount || amount + ctx.accounts.staking_account.total_reward_collected > full_amount{
-            amount = full_amount - ctx.accounts.staking_account.total_reward_collected;
+        // Check that the new lock is longer than the current one. Flex stakes have no lock to extend.
+        if ctx.accounts.staking_account.is_flex() || lock_days <= ctx.accounts.staking_account.lock_days {
+            return Err(ErrorCode::InvalidStakingPeriod.into())
         }
 
-        // Find the PDA/bump and set the signature
-        let (_mint_authority, mint_authority_bump) =
-            Pubkey::find_program_address(&[MINT_AUTHORITY_PDA_SEED, &*ctx.accounts.reward_mint.to_account_info().key.as_ref()], ctx.program_id);
+        // This also checks that the new lock is within the pool's limits
+        let duration: i64 = ctx.accounts.pool.lock_duration(lock_days)?;
 
-        let seeds = &[&MINT_AUTHORITY_PDA_SEED, &*ctx.accounts.reward_mint.to_account_info().key.as_ref(), &[mint_authority_bump]];
-        let authority_seeds = [&seeds[..]];
+        // Move the stake to the pool's count for its new lock tier
+        ctx.accounts.pool.move_lock_tier(ctx.accounts.staking_account.lock_days, lock_days)?;
 
-        // Mint the balanace due nd transfer to the user
-        token::mint_to(ctx.accounts.into_mint_to_staker().with_signer(&authority_seeds), amount as u64).unwrap();
+        // Keep what the reward streams have earned at the old lock length, since their rates follow it
+        ctx.accounts.staking_account.accrue_streams(&ctx.accounts.pool, timestamp)?;
 
-        // Update the total amount reward for the staked token and the time of the last collection
-        ctx.accounts.staking_account.last_reward_collection = timestamp;
-        ctx.accounts.staking_account.total_reward_collected = ctx.accounts.staking_account.total_reward_collected + amount;
+        if ctx.accounts.pool.mode == PoolMode::Shared {
+            // The stake's weight follows its lock length. Keep what it has earned at its old weight, and share emissions at the new weight from now on.
+            ctx.accounts.pool.update_emission(timestamp)?;
+            let pending: u128 = ctx.accounts.staking_account.shared_pending(&ctx.accounts.pool)?;
 
-        // Check if the full amount has been collected, and update the account accordingly if so
-        if ctx.accounts.staking_account.total_reward_collected + amount >= full_amount {
-            ctx.accounts.staking_account.full_reward_collected = true
+            ctx.accounts.pool.remove_stake_weight(&mut ctx.accounts.staking_account)?;
+            ctx.accounts.staking_account.lock_days = lock_days;
+            ctx.accounts.pool.add_stake_weight(&mut ctx.accounts.staking_account)?;
+            ctx.accounts.staking_account.reward_remainder = pending;
+        } else {
+            ctx.accounts.staking_account.lock_days = lock_days;
+        }
+
+        // Recompute the unstake date
+        let staking_account = &mut ctx.accounts.staking_account;
+        staking_account.unstake_date = staking_account.created + duration;
+
+        // Check whether the full reward of the new lock has already been issued
+        if ctx.accounts.pool.mode != PoolMode::Shared {
+            let full_amount: i64 = ctx.accounts.staking_account.full_reward(&ctx.accounts.pool)?;
+            ctx.accounts.staking_account.full_reward_collected = ctx.accounts.staking_account.total_reward_collected >= full_amount;
         }
 
         Ok(())
     }
 
-    // This function is run as the first step of the unstaking process. 
-    // This ensures that, whether or not the user has collected rewards along the way, that all rewards due to them are issued before the token is unstaked. 
-    pub fn collect_full(ctx: Context<CollectFull>) -> ProgramResult {
+    // Allow user to have a stake renewed for another staking period once the current one ends, instead of stopping at the full reward
+    pub fn set_auto_renew(ctx: Context<SetAutoRenew>, auto_renew: bool) -> ProgramResult {
+
+        // Flex stakes have no staking period to renew
+        if ctx.accounts.staking_account.is_flex() {
+            return Err(ErrorCode::InvalidStakingPeriod.into())
+        }
+
+        ctx.accounts.staking_account.auto_renew = auto_renew;
+
+        Ok(())
+    }
+
+    // Permissionless crank for stakes with auto_renew set: once the staking period has passed, issues whatever is still owed for it (possibly nothing)
+    // and starts a new staking period of the same length and rarity tier, leaving the token in the vault
+    pub fn renew(ctx: Context<Renew>) -> ProgramResult {
 
         // Define time-related variables
         let clock: Clock = Clock::get().unwrap();
         let timestamp = clock.unix_timestamp;
 
+        // Check that the owner asked for the stake to be renewed
+        if !ctx.accounts.staking_account.auto_renew {
+            return Err(ErrorCode::AutoRenewDisabled.into())
+        }
+
         // Ensure that the staking period has passed
         if ctx.accounts.staking_account.unstake_date > timestamp {
              return Err(ErrorCode::TooEarlyToUnstake.into())
         }
 
-        // Check that the reward has not already been fully collected
-        if ctx.accounts.staking_account.full_reward_collected == true {
-             return Err(ErrorCode::FullRewardAlreadyCollected.into())
+        // In shared emission pools, bring the pool's emissions up to date first
+        ctx.accounts.pool.update_emission(timestamp)?;
+
+        // Subtract any rewards collected along the way from the total reward amount for the finished staking period.
+        // Only what the pool's emission budget allows is paid, and the rest is forfeited, so that a used up budget does not stop the crank.
+        let (amount, remainder) = ctx.accounts.staking_account.remaining_reward(&ctx.accounts.pool, timestamp)?;
+        let paid: i64 = ctx.accounts.pool.take_emission(amount, timestamp)?;
+
+        if paid > 0 {
+            // Pay the balance due to the user, minted or transferred from the reward vault depending on the pool's reward source
+            pay_reward(
+                ctx.program_id,
+                &ctx.accounts.pool.key(),
+                ctx.accounts.pool.reward_source,
+                &ctx.accounts.reward_mint.to_account_info(),
+                &ctx.accounts.reward_mint_authority,
+                &ctx.accounts.reward_vault,
+                &ctx.accounts.owner_reward_token_account.to_account_info(),
+                &ctx.accounts.token_program.to_account_info(),
+                paid as u64,
+            )?;
+            ctx.accounts.user_stake_summary.record_reward(paid as u64)?;
         }
 
-        // Check that the staking period is valid
-        if ctx.accounts.staking_account.staking_period > 2 {
-            return Err(ErrorCode::InvalidStakingPeriod.into())
+        // Start the new staking period, carrying the fraction of a reward token left over
+        ctx.accounts.staking_account.renew(&ctx.accounts.pool, timestamp, remainder)?;
+
+        // In shared emission pools, collect_full stops a stake sharing the emissions, so it shares them again for the new lock
+        if ctx.accounts.staking_account.weight == 0 {
+            ctx.accounts.pool.add_stake_weight(&mut ctx.accounts.staking_account)?;
         }
-        
-        // Determine the full amount due for the staking period
-        let mut full_amount: i64 = 0;
-
-        if ctx.accounts.staking_account.is_one_of_one == true {
-            if ctx.accounts.staking_account.staking_period == 0 {
-                full_amount = ONE_WEEK_REWARD_OOO
-            } else if ctx.accounts.staking_account.staking_period == 1 {
-                full_amount = TWO_WEEK_REWARD_OOO
-            } else if ctx.accounts.staking_account.staking_period == 2 {
-                full_amount = FOUR_WEEK_REWARD_OOO
-            }
-        } else {
-            if ctx.accounts.staking_account.staking_period == 0 {
-                full_amount = ONE_WEEK_REWARD
-            } else if ctx.accounts.staking_account.staking_period == 1 {
-                full_amount = TWO_WEEK_REWARD
-            } else if ctx.accounts.staking_account.staking_period == 2 {
-                full_amount = FOUR_WEEK_REWARD
-            }
+
+        Ok(())
+    }
+
+    // Allow for collection of rewards over the course of staking period. 
+    // Rewards accrue per second, but at least minimum_collection_period must pass in between collection attempts
+    // The accounts of the pool's reward streams are passed through remaining_accounts, and every stream is paid too.
+    pub fn collect<'info>(ctx: Context<'_, '_, '_, 'info, Collect<'info>>) -> ProgramResult {
+
+        // Define time-related variables
+        let clock: Clock = Clock::get().unwrap();
+        let timestamp = clock.unix_timestamp;
+        let elapsed: i64 = timestamp - ctx.accounts.staking_account.last_reward_collection;
+
+        // Check that minimum collection time has elapsed
+        if elapsed < ctx.accounts.pool.minimum_collection_period {
+            return Err(ErrorCode::NotEnoughElapsedSinceLastCollection.into())
         }
 
-        if ctx.accounts.staking_account.total_reward_collected >= full_amount  {
-            return Err(ErrorCode::FullRewardAlreadyCollected.into())
+        // Check that the reward has not already been fully collected - SATISFY SOTERIA ISSUE M-2
+        if ctx.accounts.staking_account.full_reward_collected {
+             return Err(ErrorCode::FullRewardAlreadyCollected.into())
         }
 
-        // Subtract any rewards collected along the way from the total reward amount for the staking period
-        let amount: i64 = full_amount - ctx.accounts.staking_account.total_reward_collected; 
+        // In shared emission pools, bring the pool's emissions up to date first
+        ctx.accounts.pool.update_emission(timestamp)?;
 
-        // Find the PDA/bump and set the signature
-        let (_mint_authority, mint_authority_bump) =
-            Pubkey::find_program_address(&[MINT_AUTHORITY_PDA_SEED, &*ctx.accounts.reward_mint.to_account_info().key.as_ref()], ctx.program_id);
+        // Determine the reward due since the last collection
+        let (amount, remainder) = ctx.accounts.staking_account.reward_due(&ctx.accounts.pool, timestamp)?;
 
-        let seeds = &[&MINT_AUTHORITY_PDA_SEED, &*ctx.accounts.reward_mint.to_account_info().key.as_ref(), &[mint_authority_bump]];
-        let authority_seeds = [&seeds[..]];
+        // Pay as much of it as the pool's emission budget allows
+        let paid: i64 = ctx.accounts.pool.take_emission(amount, timestamp)?;
+        if paid == 0 && amount > 0 {
+            return Err(ErrorCode::EmissionBudgetExhausted.into())
+        }
 
-        // Mint the balanace due nd transfer to the user
-        token::mint_to(ctx.accounts.into_mint_to_staker().with_signer(&authority_seeds), amount as u64).unwrap();
+        // Pay the balance due to the user, minted or transferred from the reward vault depending on the pool's reward source
+        pay_reward(
+            ctx.program_id,
+            &ctx.accounts.pool.key(),
+            ctx.accounts.pool.reward_source,
+            &ctx.accounts.reward_mint.to_account_info(),
+            &ctx.accounts.reward_mint_authority,
+            &ctx.accounts.reward_vault,
+            &ctx.accounts.owner_reward_token_account.to_account_info(),
+            &ctx.accounts.token_program.to_account_info(),
+            paid as u64,
+        )?;
 
-        // Update the staking_account to show that the full reward amount has been issued
-        ctx.accounts.staking_account.total_reward_collected = ctx.accounts.staking_account.total_reward_collected + amount;
-        ctx.accounts.staking_account.full_reward_collected = true;
-        ctx.accounts.staking_account.last_reward_collection = timestamp;
+        // Update the total amount reward for the staked token, the time of the last collection and the fraction carried forward, along with anything left unpaid
+        ctx.accounts.staking_account.record_collection(&ctx.accounts.pool, paid, carry_unpaid(amount - paid, remainder)?, timestamp)?;
+        ctx.accounts.user_stake_summary.record_reward(paid as u64)?;
+
+        // Pay what the pool's reward streams have earned
+        pay_reward_streams(ctx.program_id, &ctx.accounts.pool, &mut ctx.accounts.staking_account, ctx.remaining_accounts, &ctx.accounts.token_program.to_account_info(), timestamp)?;
 
         Ok(())
     }
 
-    // This function is automatically called after successful return of collect_full, and is responsible for unstaking the token, transferring it back to the user, and closing the related staking_account
-    pub fn unstake(ctx: Context<Unstake>) -> ProgramResult {
+    // Allow for collection of rewards across several staked tokens of the same owner and pool in one transaction.
+    // Stake accounts are passed through remaining_accounts; those with nothing to collect yet are skipped.
+    pub fn collect_many<'info>(ctx: Context<'_, '_, '_, 'info, CollectMany<'info>>) -> ProgramResult {
 
         // Define time-related variables
         let clock: Clock = Clock::get().unwrap();
         let timestamp = clock.unix_timestamp;
-        
-        // Ensure that the staking period has passed, and that the full reward has been issued
-        if ctx.accounts.staking_account.unstake_date > timestamp {
-             return Err(ErrorCode::TooEarlyToUnstake.into())
-        }
 
-        // Check that the full_amount has been
-        if ctx.accounts.staking_account.full_reward_collected == false {
-             return Err(ErrorCode::FullRewardNotCollected.into())
-        }
+        let mut total_amount: i64 = 0;
+        let mut budget_exhausted = false;
 
-        // Define the vault authority and bump, and set the authority seeds to access the vault (PDA)
-        let (_vault_authority, vault_authority_bump) =
-            Pubkey::find_program_address(&[VAULT_PDA_SEED, &*ctx.accounts.staking_account.to_account_info().key.as_ref(), &*ctx.accounts.staking_mint.to_account_info().key.as_ref()], ctx.program_id);
+        // In shared emission pools, bring the pool's emissions up to date first
+        ctx.accounts.pool.update_emission(timestamp)?;
 
-        let authority_seeds = &[&VAULT_PDA_SEED, &*ctx.accounts.staking_account.to_account_info().key.as_ref(), &*ctx.accounts.staking_mint.to_account_info().key.as_ref(), &[vault_authority_bump]];
+        for staking_account_info in ctx.remaining_accounts.iter() {
+            let mut staking_account = Account::<StakeAccount>::try_from(staking_account_info)?;
 
-        // Transfer the token back to the user and close the staking_account
-        token::transfer(
-            ctx.accounts
-                .into_transfer_to_initializer_context()
-                .with_signer(&[&authority_seeds[..]]),
-            STAKING_AMOUNT,
-        )?;
+            // Check that the stake belongs to the owner and pool
+            if staking_account.staking_token_owner != *ctx.accounts.staking_token_owner.key
+                || staking_account.pool != ctx.accounts.pool.key()
+                || !staking_account_info.is_writable
+            {
+                return Err(ErrorCode::InvalidCollectManyAccounts.into())
+            }
 
-        token::close_account(
-            ctx.accounts
-                .into_close_context()
-                .with_signer(&[&authority_seeds[..]]),
-        )?;
+            // Skip stakes that have been fully collected, or that were collected less than the minimum collection time ago
+            if staking_account.full_reward_collected
+                || timestamp - staking_account.last_reward_collection < ctx.accounts.pool.minimum_collection_period
+            {
+                continue
+            }
+
+            let (amount, remainder) = staking_account.reward_due(&ctx.accounts.pool, timestamp)?;
+
+            // Pay as much as the pool's emission budget allows. Stakes it cannot pay anything are skipped, and keep what they are owed.
+            let paid: i64 = ctx.accounts.pool.take_emission(amount, timestamp)?;
+            if paid == 0 && amount > 0 {
+                budget_exhausted = true;
+                continue
+            }
+
+            staking_account.record_collection(&ctx.accounts.pool, paid, carry_unpaid(amount - paid, remainder)?, timestamp)?;
+            total_amount = total_amount.checked_add(paid).ok_or(ErrorCode::MathOverflow)?;
+
+            // Write the record back immediately, so that a stake account passed twice is skipped the second time
+            staking_account.exit(ctx.program_id)?;
+        }
+
+        // Check that at least one of the stakes had a reward to collect
+        if total_amount == 0 && budget_exhausted {
+            return Err(ErrorCode::EmissionBudgetExhausted.into())
+        }
+
+        if total_amount == 0 {
+            return Err(ErrorCode::NotEnoughElapsedSinceLastCollection.into())
+        }
+
+        // Pay the balance due to the user, minted or transferred from the reward vault depending on the pool's reward source
+        pay_reward(
+            ctx.program_id,
+            &ctx.accounts.pool.key(),
+            ctx.accounts.pool.reward_source,
+            &ctx.accounts.reward_mint.to_account_info(),
+            &ctx.accounts.reward_mint_authority,
+            &ctx.accounts.reward_vault,
+            &ctx.accounts.owner_reward_token_account.to_account_info(),
+            &ctx.accounts.token_program.to_account_info(),
+            total_amount as u64,
+        )?;
+        ctx.accounts.user_stake_summary.record_reward(total_amount as u64)?;
+
+        Ok(())
+    }
+
+    // This function is run as the first step of the unstaking process. 
+    // This ensures that, whether or not the user has collected rewards along the way, that all rewards due to them are issued before the token is unstaked. 
+    // The accounts of the pool's reward streams are passed through remaining_accounts, and every stream is paid too.
+    pub fn collect_full<'info>(ctx: Context<'_, '_, '_, 'info, CollectFull<'info>>) -> ProgramResult {
+
+        // Define time-related variables
+        let clock: Clock = Clock::get().unwrap();
+        let timestamp = clock.unix_timestamp;
+
+        // Ensure that the staking period has passed
+        if ctx.accounts.staking_account.unstake_date > timestamp {
+             return Err(ErrorCode::TooEarlyToUnstake.into())
+        }
+
+        // Check that the reward has not already been fully collected
+        if ctx.accounts.staking_account.full_reward_collected {
+             return Err(ErrorCode::FullRewardAlreadyCollected.into())
+        }
+
+        // In shared emission pools, bring the pool's emissions up to date first
+        ctx.accounts.pool.update_emission(timestamp)?;
+
+        // Subtract any rewards collected along the way from the total reward amount for the staking period.
+        // Only flex and shared emission stakes can have nothing left here, since a fixed reward that has been fully collected is flagged above.
+        let (amount, remainder) = ctx.accounts.staking_account.remaining_reward(&ctx.accounts.pool, timestamp)?;
+
+        // Pay as much of it as the pool's emission budget allows
+        let paid: i64 = ctx.accounts.pool.take_emission(amount, timestamp)?;
+        if paid == 0 && amount > 0 {
+            return Err(ErrorCode::EmissionBudgetExhausted.into())
+        }
+
+        if paid > 0 {
+            // Pay the balance due to the user, minted or transferred from the reward vault depending on the pool's reward source
+            pay_reward(
+                ctx.program_id,
+                &ctx.accounts.pool.key(),
+                ctx.accounts.pool.reward_source,
+                &ctx.accounts.reward_mint.to_account_info(),
+                &ctx.accounts.reward_mint_authority,
+                &ctx.accounts.reward_vault,
+                &ctx.accounts.owner_reward_token_account.to_account_info(),
+                &ctx.accounts.token_program.to_account_info(),
+                paid as u64,
+            )?;
+        }
+        ctx.accounts.user_stake_summary.record_reward(paid as u64)?;
+
+        if paid < amount {
+            // The budget only covered part of the reward. Record it as a collection and keep the rest owed, so the full reward can be collected later.
+            ctx.accounts.staking_account.record_collection(&ctx.accounts.pool, paid, carry_unpaid(amount - paid, remainder)?, timestamp)?;
+        } else {
+            // Update the staking_account to show that the full reward amount has been issued, and stop it sharing emissions
+            ctx.accounts.staking_account.total_reward_collected = ctx.accounts.staking_account.total_reward_collected.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+            ctx.accounts.staking_account.full_reward_collected = true;
+            ctx.accounts.pool.remove_stake_weight(&mut ctx.accounts.staking_account)?;
+            ctx.accounts.staking_account.last_reward_collection = timestamp;
+        }
+
+        // Pay what the pool's reward streams have earned
+        pay_reward_streams(ctx.program_id, &ctx.accounts.pool, &mut ctx.accounts.staking_account, ctx.remaining_accounts, &ctx.accounts.token_program.to_account_info(), timestamp)?;
+
+        Ok(())
+    }
+
+    // This function is automatically called after successful return of collect_full, and is responsible for unstaking the token, transferring it back to the user, and closing the related staking_account
+    // The accounts of the pool's reward streams are passed through remaining_accounts, and what the streams have earned since collect_full is paid too.
+    pub fn unstake<'info>(ctx: Context<'_, '_, '_, 'info, Unstake<'info>>) -> ProgramResult {
+
+        // Define time-related variables
+        let clock: Clock = Clock::get().unwrap();
+        let timestamp = clock.unix_timestamp;
+        
+        // Ensure that the staking period has passed, and that the full reward has been issued
+        if ctx.accounts.staking_account.unstake_date > timestamp {
+             return Err(ErrorCode::TooEarlyToUnstake.into())
+        }
+
+        // Check that the full_amount has been
+        if !ctx.accounts.staking_account.full_reward_collected {
+             return Err(ErrorCode::FullRewardNotCollected.into())
+        }
+
+        // Pay what the pool's reward streams have earned, as the staking_account is closed on exit
+        pay_reward_streams(ctx.program_id, &ctx.accounts.pool, &mut ctx.accounts.staking_account, ctx.remaining_accounts, &ctx.accounts.token_program.to_account_info(), timestamp)?;
+
+        // Stop the token sharing the pool's revenue. Its revenue has to be claimed first.
+        ctx.accounts.pool.remove_staked_token(&ctx.accounts.staking_account, timestamp)?;
+        ctx.accounts.user_stake_summary.remove_stake(timestamp)?;
+
+        // Define the vault authority and bump, and set the authority seeds to access the vault (PDA)
+        let (_vault_authority, vault_authority_bump) =
+            Pubkey::find_program_address(&[VAULT_PDA_SEED, ctx.accounts.staking_account.to_account_info().key.as_ref(), ctx.accounts.staking_mint.to_account_info().key.as_ref()], ctx.program_id);
+
+        let authority_seeds = &[VAULT_PDA_SEED, ctx.accounts.staking_account.to_account_info().key.as_ref(), ctx.accounts.staking_mint.to_account_info().key.as_ref(), &[vault_authority_bump]];
+
+        // Transfer the token back to the user and close the staking_account
+        token::transfer(
+            ctx.accounts
+                .into_transfer_to_initializer_context()
+                .with_signer(&[&authority_seeds[..]]),
+            STAKING_AMOUNT,
+        )?;
+
+        token::close_account(
+            ctx.accounts
+                .into_close_context()
+                .with_signer(&[&authority_seeds[..]]),
+        )?;
+
+        Ok(())
+    }
+
+    // Combines collect_full and unstake: issues whatever is still owed for the staking period (possibly nothing),
+    // transfers the token back to the user, and closes the vault and the related staking_account
+    // The accounts of the pool's reward streams are passed through remaining_accounts, and every stream is paid too.
+    pub fn claim_and_unstake<'info>(ctx: Context<'_, '_, '_, 'info, ClaimAndUnstake<'info>>) -> ProgramResult {
+
+        // Define time-related variables
+        let clock: Clock = Clock::get().unwrap();
+        let timestamp = clock.unix_timestamp;
+
+        // Ensure that the staking period has passed
+        if ctx.accounts.staking_account.unstake_date > timestamp {
+             return Err(ErrorCode::TooEarlyToUnstake.into())
+        }
+
+        // In shared emission pools, bring the pool's emissions up to date first
+        ctx.accounts.pool.update_emission(timestamp)?;
+
+        // Subtract any rewards collected along the way from the total reward amount for the staking period.
+        // Only what the pool's emission budget allows is paid, and the rest is forfeited, so that the token can always be unstaked.
+        let (amount, _remainder) = ctx.accounts.staking_account.remaining_reward(&ctx.accounts.pool, timestamp)?;
+        let paid: i64 = ctx.accounts.pool.take_emission(amount, timestamp)?;
+
+        if paid > 0 {
+            // Pay the balance due to the user, minted or transferred from the reward vault depending on the pool's reward source
+            pay_reward(
+                ctx.program_id,
+                &ctx.accounts.pool.key(),
+                ctx.accounts.pool.reward_source,
+                &ctx.accounts.reward_mint.to_account_info(),
+                &ctx.accounts.reward_mint_authority,
+                &ctx.accounts.reward_vault,
+                &ctx.accounts.owner_reward_token_account.to_account_info(),
+                &ctx.accounts.token_program.to_account_info(),
+                paid as u64,
+            )?;
+            ctx.accounts.user_stake_summary.record_reward(paid as u64)?;
+        }
+
+        // Pay what the pool's reward streams have earned
+        pay_reward_streams(ctx.program_id, &ctx.accounts.pool, &mut ctx.accounts.staking_account, ctx.remaining_accounts, &ctx.accounts.token_program.to_account_info(), timestamp)?;
+
+        // Stop the stake sharing emissions and revenue. Its revenue has to be claimed first.
+        ctx.accounts.pool.remove_stake_weight(&mut ctx.accounts.staking_account)?;
+        ctx.accounts.pool.remove_staked_token(&ctx.accounts.staking_account, timestamp)?;
+        ctx.accounts.user_stake_summary.remove_stake(timestamp)?;
+
+        // Define the vault authority and bump, and set the authority seeds to access the vault (PDA)
+        let (_vault_authority, vault_authority_bump) =
+            Pubkey::find_program_address(&[VAULT_PDA_SEED, ctx.accounts.staking_account.to_account_info().key.as_ref(), ctx.accounts.staking_mint.to_account_info().key.as_ref()], ctx.program_id);
+
+        let authority_seeds = &[VAULT_PDA_SEED, ctx.accounts.staking_account.to_account_info().key.as_ref(), ctx.accounts.staking_mint.to_account_info().key.as_ref(), &[vault_authority_bump]];
+
+        // Transfer the token back to the user, close the vault, and let the staking_account be closed on exit
+        token::transfer(
+            ctx.accounts
+                .into_transfer_to_initializer_context()
+                .with_signer(&[&authority_seeds[..]]),
+            STAKING_AMOUNT,
+        )?;
+
+        token::close_account(
+            ctx.accounts
+                .into_close_context()
+                .with_signer(&[&authority_seeds[..]]),
+        )?;
+
+        Ok(())
+    }
+
+    // Allow user to take the token back before the end of the staking period. Any reward that has not been collected yet is forfeited,
+    // and the pool's early unstake penalty for the staking period is paid to the treasury before the token is returned
+    pub fn early_unstake(ctx: Context<EarlyUnstake>) -> ProgramResult {
+
+        // Define time-related variables
+        let clock: Clock = Clock::get().unwrap();
+        let timestamp = clock.unix_timestamp;
+
+        // Once the staking period has passed the token can be unstaked with its full reward, so there is nothing to forfeit
+        if ctx.accounts.staking_account.unstake_date <= timestamp {
+             return Err(ErrorCode::StakingPeriodEnded.into())
+        }
+
+        // In shared emission pools, bring the pool's emissions up to date and stop the stake sharing them. Its unpaid share is forfeited.
+        ctx.accounts.pool.update_emission(timestamp)?;
+        ctx.accounts.pool.remove_stake_weight(&mut ctx.accounts.staking_account)?;
+        ctx.accounts.pool.remove_staked_token(&ctx.accounts.staking_account, timestamp)?;
+        ctx.accounts.user_stake_summary.remove_stake(timestamp)?;
+
+        let penalty: u64 = ctx.accounts.pool.early_unstake_penalty(ctx.accounts.staking_account.unstake_date - timestamp)?;
+
+        if penalty > 0 {
+            match ctx.accounts.pool.early_unstake_penalty_kind {
+                PenaltyKind::RewardToken => {
+                    // Check that the treasury account is the treasury's reward token account
+                    let treasury = Account::<TokenAccount>::try_from(&ctx.accounts.treasury)?;
+                    if treasury.owner != ctx.accounts.pool.treasury || treasury.mint != ctx.accounts.pool.reward_mint {
+                        return Err(ErrorCode::InvalidTreasury.into())
+                    }
+
+                    token::transfer(ctx.accounts.into_transfer_penalty_context(), penalty)?;
+                }
+                PenaltyKind::Sol => {
+                    if *ctx.accounts.treasury.key != ctx.accounts.pool.treasury {
+                        return Err(ErrorCode::InvalidTreasury.into())
+                    }
+
+                    invoke(
+                        &system_instruction::transfer(ctx.accounts.staking_token_owner.key, ctx.accounts.treasury.key, penalty),
+                        &[
+                            ctx.accounts.staking_token_owner.to_account_info(),
+                            ctx.accounts.treasury.clone(),
+                            ctx.accounts.system_program.to_account_info(),
+                        ],
+                    )?;
+                }
+                PenaltyKind::None => {}
+            }
+        }
+
+        // Define the vault authority and bump, and set the authority seeds to access the vault (PDA)
+        let (_vault_authority, vault_authority_bump) =
+            Pubkey::find_program_address(&[VAULT_PDA_SEED, ctx.accounts.staking_account.to_account_info().key.as_ref(), ctx.accounts.staking_mint.to_account_info().key.as_ref()], ctx.program_id);
+
+        let authority_seeds = &[VAULT_PDA_SEED, ctx.accounts.staking_account.to_account_info().key.as_ref(), ctx.accounts.staking_mint.to_account_info().key.as_ref(), &[vault_authority_bump]];
+
+        // Transfer the token back to the user, close the vault, and let the staking_account be closed on exit
+        token::transfer(
+            ctx.accounts
+                .into_transfer_to_initializer_context()
+                .with_signer(&[&authority_seeds[..]]),
+            STAKING_AMOUNT,
+        )?;
+
+        token::close_account(
+            ctx.accounts
+                .into_close_context()
+                .with_signer(&[&authority_seeds[..]]),
+        )?;
 
         Ok(())
     }
 }
 
+#[derive(Accounts)]
+#[instruction(collection: Pubkey)]
+pub struct InitializePool<'info> {
+    #[account(
+        init,
+        seeds = [POOL_PDA_SEED, collection.as_ref()],
+        bump,
+        payer = authority,
+        space = StakingPool::LEN,
+    )]
+    pub pool: Box<Account<'info, StakingPool>>,
+    pub reward_mint: Account<'info, Mint>,
+    /// CHECK: this is safe because it is only stored as the admin of the pool
+    pub admin: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = *authority.to_account_info().key == AUTHORITY_INIT,
+    )]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePool<'info> {
+    #[account(mut, has_one = admin)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    pub admin: Signer<'info>,
+}
+
 #[derive(Accounts)]
 pub struct AuthorityInit<'info> {
     #[account(
         init,
-        seeds = [MINT_AUTHORITY_PDA_SEED, reward_mint.key().as_ref()],
+        seeds = [MINT_AUTHORITY_PDA_SEED, pool.key().as_ref(), reward_mint.key().as_ref()],
         bump,
         payer = admin,
         space = 8 + 8,
     )]
     pub mint_authority: AccountInfo<'info>,
     #[account(
-        constraint = *reward_mint.to_account_info().key == MINT_ADDRESS,
+        has_one = admin,
+        has_one = reward_mint,
     )]
+    pub pool: Box<Account<'info, StakingPool>>,
     pub reward_mint: Account<'info, Mint>,
-    #[account(
-        mut,
-        constraint = *admin.to_account_info().key == AUTHORITY_INIT,
-    )]
+    #[account(mut)]
     pub admin: Signer<'info>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-#[instruction(staking_period: u16, is_one_of_one: bool)]
-pub struct Stake<'info> {
-    #[account(mut)] 
-    pub staking_token_owner: Signer<'info>,
-    pub staking_mint: Account<'info, Mint>, 
+pub struct RewardVaultInit<'info> {
     #[account(
         init,
-        seeds = [STAKING_ACCOUNT_PDA_SEED, staking_account.key().as_ref(), staking_mint.key().as_ref()],
+        seeds = [REWARD_VAULT_PDA_SEED, pool.key().as_ref(), reward_mint.key().as_ref()],
         bump,
-        payer = staking_token_owner,
-        token::mint = staking_mint,
-        token::authority = staking_token_owner,
+        payer = admin,
+        token::mint = reward_mint,
+        token::authority = reward_mint_authority,
     )]
-    pub vault_account: Account<'info, TokenAccount>,
+    pub reward_vault: Account<'info, TokenAccount>,
+    /// CHECK: this is safe because it is a PDA bound to this program, and only set as the owner of the reward vault
+    #[account(
+        seeds = [MINT_AUTHORITY_PDA_SEED, pool.key().as_ref(), reward_mint.key().as_ref()],
+        bump,
+    )]
+    pub reward_mint_authority: AccountInfo<'info>,
     #[account(
         mut,
-        constraint = owner_staking_token_account.amount == STAKING_AMOUNT
+        has_one = admin,
+        has_one = reward_mint,
     )]
-    pub owner_staking_token_account: Account<'info, TokenAccount>,
-    pub owner_reward_token_account: Account<'info, TokenAccount>,
-    #[account(init, payer = staking_token_owner, space = StakeAccount::LEN)]
-    pub staking_account: Box<Account<'info, StakeAccount>>,
+    pub pool: Box<Account<'info, StakingPool>>,
+    pub reward_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
     pub token_program: Program<'info, Token>,
 }
 
-impl<'info> Stake<'info> {
-    fn into_transfer_to_pda_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
-            from: self
-            .owner_staking_token_account
-            .to_account_info()
-            .clone(),
-            to: self.vault_account.to_account_info().clone(),
-            authority: self.staking_token_owner.to_account_info().clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
-    }
-    
-    fn into_set_authority_context(&self) -> CpiContext<'_, '_, '_, 'info, SetAuthority<'info>> {
-        let cpi_accounts = SetAuthority {
-            account_or_mint: self.vault_account.to_account_info().clone(),
-            current_authority: self.staking_token_owner.to_account_info().clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
-    }
+#[derive(Accounts)]
+pub struct RequestMintAuthorityRelease<'info> {
+    #[account(mut, has_one = admin)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    pub admin: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct Collect<'info> {
+pub struct CancelMintAuthorityRelease<'info> {
+    #[account(mut, has_one = admin)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseMintAuthority<'info> {
     /// CHECK: this is safe because it is a PDA bound to this program
     #[account(
-        mut,
-        seeds = [MINT_AUTHORITY_PDA_SEED, reward_mint.key().as_ref()],
+        seeds = [MINT_AUTHORITY_PDA_SEED, pool.key().as_ref(), reward_mint.key().as_ref()],
         bump,
     )]
     pub reward_mint_authority: AccountInfo<'info>,
-    #[account(mut)]
-    pub staking_token_owner: Signer<'info>,
-    #[account(
-        constraint = staking_account.owner_staking_token_account == *owner_staking_token_account.to_account_info().key,
-    )]
-    pub owner_staking_token_account: Account<'info, TokenAccount>,
     #[account(
         mut,
-        constraint = staking_account.staking_token_owner == *staking_token_owner.key,
-        constraint = staking_account.owner_staking_token_account == *owner_staking_token_account.to_account_info().key,
-        constraint = staking_account.staking_mint == *staking_mint.to_account_info().key,
-    )]
-    pub staking_account: Box<Account<'info, StakeAccount>>,
-    pub staking_mint: Account<'info, Mint>,
-    #[account(
-        constraint = *reward_mint.to_account_info().key == MINT_ADDRESS,
+        has_one = admin,
+        has_one = reward_mint,
     )]
+    pub pool: Box<Account<'info, StakingPool>>,
+    #[account(mut)]
     pub reward_mint: Account<'info, Mint>,
-    #[account(
-        mut,
-        constraint = staking_account.owner_reward_token_account == *owner_reward_token_account.to_account_info().key,
-    )]
-    pub owner_reward_token_account: Account<'info, TokenAccount>,
+    pub admin: Signer<'info>,
     pub token_program: Program<'info, Token>,
 }
 
-impl<'info> Collect<'info> {
-    fn into_mint_to_staker(&self) -> CpiContext<'_, '_, '_, 'info, MintTo<'info>> {
-        let cpi_accounts = MintTo {
-            mint: self.reward_mint.to_account_info().clone(),
-            to: self.owner_reward_token_account.to_account_info().clone(),
-            authority: self.reward_mint_authority.to_account_info().clone(),
+impl<'info> ReleaseMintAuthority<'info> {
+    fn into_set_authority_context(&self) -> CpiContext<'_, '_, '_, 'info, SetAuthority<'info>> {
+        let cpi_accounts = SetAuthority {
+            account_or_mint: self.reward_mint.to_account_info().clone(),
+            current_authority: self.reward_mint_authority.clone(),
         };
         CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
     }
 }
 
 #[derive(Accounts)]
-pub struct CollectFull<'info> {
-    /// CHECK: this is safe because it is a PDA bound to this program
+pub struct AddRewardStream<'info> {
     #[account(
-        mut,
-        seeds = [MINT_AUTHORITY_PDA_SEED, reward_mint.key().as_ref()],
+        init,
+        seeds = [REWARD_VAULT_PDA_SEED, pool.key().as_ref(), stream_mint.key().as_ref()],
         bump,
+        payer = admin,
+        token::mint = stream_mint,
+        token::authority = stream_mint_authority,
     )]
-    pub reward_mint_authority: AccountInfo<'info>,
-    #[account(mut)]
-    pub staking_token_owner: Signer<'info>,
+    pub stream_vault: Account<'info, TokenAccount>,
+    /// CHECK: this is safe because it is a PDA bound to this program, and only set as the owner of the stream vault
     #[account(
-        constraint = staking_account.owner_staking_token_account == *owner_staking_token_account.to_account_info().key,
+        seeds = [MINT_AUTHORITY_PDA_SEED, pool.key().as_ref(), stream_mint.key().as_ref()],
+        bump,
     )]
-    pub owner_staking_token_account: Account<'info, TokenAccount>,
+    pub stream_mint_authority: AccountInfo<'info>,
+    #[account(mut, has_one = admin)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    pub stream_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct AddRevenueToken<'info> {
+    #[account(mut, has_one = admin)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    /// CHECK: this is safe because it is either SOL_REVENUE_MINT or deserialized as a mint before its vault is created
+    pub revenue_mint: AccountInfo<'info>,
+    /// CHECK: this is safe because it is a PDA bound to this program, created here as a system account or token account
     #[account(
         mut,
-        constraint = staking_account.staking_token_owner == *staking_token_owner.key,
-        constraint = staking_account.owner_staking_token_account == *owner_staking_token_account.to_account_info().key,
-        constraint = staking_account.staking_mint == *staking_mint.to_account_info().key,
+        seeds = [REVENUE_VAULT_PDA_SEED, pool.key().as_ref(), revenue_mint.key().as_ref()],
+        bump,
+    )]
+    pub revenue_vault: AccountInfo<'info>,
+    /// CHECK: this is safe because it is a PDA bound to this program, and only set as the owner of the revenue vault
+    #[account(
+        seeds = [MINT_AUTHORITY_PDA_SEED, pool.key().as_ref(), revenue_mint.key().as_ref()],
+        bump,
+    )]
+    pub revenue_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct DepositRevenue<'info> {
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+    /// CHECK: this is safe because it is only used as the source of a token transfer signed by the depositor, and unused for SOL
+    #[account(mut)]
+    pub depositor_token_account: AccountInfo<'info>,
+    #[account(mut)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    /// CHECK: this is safe because it is matched against the pool's revenue tokens
+    pub revenue_mint: AccountInfo<'info>,
+    /// CHECK: this is safe because it is a PDA bound to this program
+    #[account(
+        mut,
+        seeds = [REVENUE_VAULT_PDA_SEED, pool.key().as_ref(), revenue_mint.key().as_ref()],
+        bump,
+    )]
+    pub revenue_vault: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> DepositRevenue<'info> {
+    fn into_transfer_to_vault_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.depositor_token_account.clone(),
+            to: self.revenue_vault.clone(),
+            authority: self.depositor.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+}
+
+#[derive(Accounts)]
+pub struct ClaimRevenue<'info> {
+    #[account(mut)]
+    pub staking_token_owner: Signer<'info>,
+    #[account(
+        mut,
+        constraint = staking_account.staking_token_owner == *staking_token_owner.key,
+        has_one = pool,
     )]
     pub staking_account: Box<Account<'info, StakeAccount>>,
-    pub staking_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    /// CHECK: this is safe because it is matched against the pool's revenue tokens
+    pub revenue_mint: AccountInfo<'info>,
+    /// CHECK: this is safe because it is a PDA bound to this program
     #[account(
-        constraint = *reward_mint.to_account_info().key == MINT_ADDRESS,
+        mut,
+        seeds = [REVENUE_VAULT_PDA_SEED, pool.key().as_ref(), revenue_mint.key().as_ref()],
+        bump,
     )]
-    pub reward_mint: Account<'info, Mint>,
+    pub revenue_vault: AccountInfo<'info>,
+    /// CHECK: this is safe because it is a PDA bound to this program
+    #[account(
+        seeds = [MINT_AUTHORITY_PDA_SEED, pool.key().as_ref(), revenue_mint.key().as_ref()],
+        bump,
+    )]
+    pub revenue_authority: AccountInfo<'info>,
+    /// CHECK: this is safe because it is only used as the destination of a token transfer, and unused for SOL
+    #[account(mut)]
+    pub owner_revenue_token_account: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> ClaimRevenue<'info> {
+    fn into_transfer_to_owner_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.revenue_vault.clone(),
+            to: self.owner_revenue_token_account.clone(),
+            authority: self.revenue_authority.clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+}
+
+#[derive(Accounts)]
+pub struct FundPool<'info> {
+    pub funder: Signer<'info>,
+    #[account(mut)]
+    pub funder_token_account: Account<'info, TokenAccount>,
+    pub pool: Box<Account<'info, StakingPool>>,
     #[account(
         mut,
-        constraint = staking_account.owner_reward_token_account == *owner_reward_token_account.to_account_info().key,
+        seeds = [REWARD_VAULT_PDA_SEED, pool.key().as_ref(), funder_token_account.mint.as_ref()],
+        bump,
     )]
-    pub owner_reward_token_account: Account<'info, TokenAccount>,
+    pub reward_vault: Account<'info, TokenAccount>,
     pub token_program: Program<'info, Token>,
 }
 
-impl<'info> CollectFull<'info> {
-    fn into_mint_to_staker(&self) -> CpiContext<'_, '_, '_, 'info, MintTo<'info>> {
-        let cpi_accounts = MintTo {
-            mint: self.reward_mint.to_account_info().clone(),
-            to: self.owner_reward_token_account.to_account_info().clone(),
-            authority: self.reward_mint_authority.to_account_info().clone(),
+impl<'info> FundPool<'info> {
+    fn into_transfer_to_vault_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.funder_token_account.to_account_info().clone(),
+            to: self.reward_vault.to_account_info().clone(),
+            authority: self.funder.to_account_info().clone(),
         };
         CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
     }
 }
 
 #[derive(Accounts)]
-pub struct Unstake<'info> {
+pub struct SetRarity<'info> {
+    #[account(
+        init_if_needed,
+        seeds = [RARITY_PDA_SEED, pool.key().as_ref(), mint.key().as_ref()],
+        bump,
+        payer = admin,
+        space = RarityRecord::LEN,
+    )]
+    pub rarity_record: Account<'info, RarityRecord>,
+    #[account(has_one = admin)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    pub mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveRarity<'info> {
+    #[account(
+        mut,
+        has_one = pool,
+        close = admin,
+    )]
+    pub rarity_record: Account<'info, RarityRecord>,
+    #[account(has_one = admin)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetEarlyUnstakePenalty<'info> {
+    #[account(mut, has_one = admin)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    /// CHECK: this is safe because it is only stored as the wallet receiving early unstake penalties
+    pub treasury: AccountInfo<'info>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetFlexMode<'info> {
+    #[account(mut, has_one = admin)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetEmissionBudget<'info> {
+    #[account(mut, has_one = admin)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetEmissionSchedule<'info> {
+    #[account(mut, has_one = admin)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(lock_days: u16)]
+pub struct Stake<'info> {
     #[account(mut)] 
     pub staking_token_owner: Signer<'info>,
     pub staking_mint: Account<'info, Mint>, 
+    /// CHECK: this is safe because it is deserialized by the Token Metadata program layout, which checks the owner, and matched against the staking_mint
+    pub staking_mint_metadata: AccountInfo<'info>,
     #[account(
-        mut,
+        init,
         seeds = [STAKING_ACCOUNT_PDA_SEED, staking_account.key().as_ref(), staking_mint.key().as_ref()],
-        bump
+        bump,
+        payer = staking_token_owner,
+        token::mint = staking_mint,
+        token::authority = staking_token_owner,
     )]
     pub vault_account: Account<'info, TokenAccount>,
-    /// CHECK: this is safe because it is calculated by the client
     #[account(
-        seeds = [VAULT_PDA_SEED, staking_account.to_account_info().key().as_ref()],
-        bump
-    )]
-    pub vault_authority: AccountInfo<'info>,
-    #[account(mut, 
-        seeds = [STAKING_ACCOUNT_PDA_SEED, staking_account.key().as_ref(), staking_mint.key().as_ref()],
-        bump,
+        mut,
+        constraint = owner_staking_token_account.amount == STAKING_AMOUNT
     )]
     pub owner_staking_token_account: Account<'info, TokenAccount>,
+    pub owner_reward_token_account: Account<'info, TokenAccount>,
     #[account(
-        mut,
-        constraint = staking_account.staking_token_owner == *staking_token_owner.key,
-        constraint = staking_account.owner_staking_token_account == *owner_staking_token_account.to_account_info().key,
-        constraint = staking_account.staking_mint == *staking_mint.to_account_info().key,
-        close = staking_token_owner
-    )] 
+        init,
+        seeds = [STAKE_PDA_SEED, pool.key().as_ref(), staking_mint.key().as_ref()],
+        bump,
+        payer = staking_token_owner,
+        space = StakeAccount::LEN,
+    )]
     pub staking_account: Box<Account<'info, StakeAccount>>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_SUMMARY_PDA_SEED, pool.key().as_ref(), staking_token_owner.key().as_ref()],
+        bump,
+        payer = staking_token_owner,
+        space = UserStakeSummary::LEN,
+    )]
+    pub user_stake_summary: Box<Account<'info, UserStakeSummary>>,
+    #[account(mut)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    /// CHECK: this is safe because the address is derived from the pool and staking_mint, and the record only counts if it is owned by this program
+    #[account(
+        seeds = [RARITY_PDA_SEED, pool.key().as_ref(), staking_mint.key().as_ref()],
+        bump,
+    )]
+    pub rarity_record: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
     pub token_program: Program<'info, Token>,
 }
 
-impl<'info> Unstake<'info> {
-    fn into_transfer_to_initializer_context(
-        &self,
-    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+impl<'info> Stake<'info> {
+    fn into_transfer_to_pda_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
         let cpi_accounts = Transfer {
-            from: self.vault_account.to_account_info().clone(),
-            to: self
+            from: self
             .owner_staking_token_account
             .to_account_info()
             .clone(),
-            authority: self.vault_authority.clone(),
+            to: self.vault_account.to_account_info().clone(),
+            authority: self.staking_token_owner.to_account_info().clone(),
         };
         CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
     }
     
-    fn into_close_context(&self) -> CpiContext<'_, '_, '_, 'info, CloseAccount<'info>> {
-        let cpi_accounts = CloseAccount {
-            account: self.vault_account.to_account_info().clone(),
-            destination: self.staking_token_owner.to_account_info().clone(),
-            authority: self.vault_authority.clone(),
+    fn into_set_authority_context(&self) -> CpiContext<'_, '_, '_, 'info, SetAuthority<'info>> {
+        let cpi_accounts = SetAuthority {
+            account_or_mint: self.vault_account.to_account_info().clone(),
+            current_authority: self.staking_token_owner.to_account_info().clone(),
         };
         CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
     }
 }
 
-#[account]
-pub struct StakeAccount {
-    pub staking_token_owner: Pubkey,
-    pub owner_staking_token_account: Pubkey,
-    pub staking_mint: Pubkey,
-    pub created: i64,
-    pub unstake_date: i64,
-    pub staking_period: u16,
-    pub is_one_of_one: bool,
-    pub full_reward_collected: bool,
-    pub last_reward_collection: i64,
-    pub total_reward_collected: i64,
-    pub owner_reward_token_account: Pubkey,
+#[derive(Accounts)]
+#[instruction(lock_days: u16)]
+pub struct StakeMany<'info> {
+    #[account(mut)]
+    pub staking_token_owner: Signer<'info>,
+    pub owner_reward_token_account: Account<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_SUMMARY_PDA_SEED, pool.key().as_ref(), staking_token_owner.key().as_ref()],
+        bump,
+        payer = staking_token_owner,
+        space = UserStakeSummary::LEN,
+    )]
+    pub user_stake_summary: Box<Account<'info, UserStakeSummary>>,
+    #[account(mut)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Program<'info, Token>,
 }
 
-impl StakeAccount {
-    const LEN: usize = DISCRIMINATOR_LENGTH
-        + PUBLIC_KEY_LENGTH // owner
-        + PUBLIC_KEY_LENGTH // owner_staking_token_account
-        + PUBLIC_KEY_LENGTH // owner_reward_token_account
-        + PUBLIC_KEY_LENGTH // mint
-        + TIMESTAMP_LENGTH // created
-        + TIMESTAMP_LENGTH // last_reward_collection
-        + 1 // total_reward_collected
-        + TIMESTAMP_LENGTH // unstake_date
-        + 32 // staking period
-        + 1 // is one of one
-        + 8; // reward collected
+impl<'info> StakeMany<'info> {
+    fn into_transfer_to_pda_context(
+        &self,
+        owner_staking_token_account: AccountInfo<'info>,
+        vault_account: AccountInfo<'info>,
+    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: owner_staking_token_account,
+            to: vault_account,
+            authority: self.staking_token_owner.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+}
+
+#[derive(Accounts)]
+pub struct ExtendStake<'info> {
+    pub staking_token_owner: Signer<'info>,
+    #[account(
+        mut,
+        constraint = staking_account.staking_token_owner == *staking_token_owner.key,
+        has_one = pool,
+    )]
+    pub staking_account: Box<Account<'info, StakeAccount>>,
+    #[account(mut)]
+    pub pool: Box<Account<'info, StakingPool>>,
+}
+
+#[derive(Accounts)]
+pub struct SetAutoRenew<'info> {
+    pub staking_token_owner: Signer<'info>,
+    #[account(
+        mut,
+        constraint = staking_account.staking_token_owner == *staking_token_owner.key,
+    )]
+    pub staking_account: Box<Account<'info, StakeAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct Renew<'info> {
+    /// CHECK: this is safe because it is a PDA bound to this program
+    #[account(
+        mut,
+        seeds = [MINT_AUTHORITY_PDA_SEED, pool.key().as_ref(), reward_mint.key().as_ref()],
+        bump,
+    )]
+    pub reward_mint_authority: AccountInfo<'info>,
+    pub cranker: Signer<'info>,
+    #[account(
+        mut,
+        has_one = pool,
+    )]
+    pub staking_account: Box<Account<'info, StakeAccount>>,
+    #[account(
+        mut,
+        seeds = [USER_SUMMARY_PDA_SEED, pool.key().as_ref(), staking_account.staking_token_owner.as_ref()],
+        bump,
+    )]
+    pub user_stake_summary: Box<Account<'info, UserStakeSummary>>,
+    #[account(mut, has_one = reward_mint)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    #[account(mut)]
+    pub reward_mint: Account<'info, Mint>,
+    /// CHECK: this is safe because it is a PDA bound to this program, and is only used as a token account when the pool pays rewards from its vault
+    #[account(
+        mut,
+        seeds = [REWARD_VAULT_PDA_SEED, pool.key().as_ref(), reward_mint.key().as_ref()],
+        bump,
+    )]
+    pub reward_vault: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = staking_account.owner_reward_token_account == *owner_reward_token_account.to_account_info().key,
+    )]
+    pub owner_reward_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct Collect<'info> {
+    /// CHECK: this is safe because it is a PDA bound to this program
+    #[account(
+        mut,
+        seeds = [MINT_AUTHORITY_PDA_SEED, pool.key().as_ref(), reward_mint.key().as_ref()],
+        bump,
+    )]
+    pub reward_mint_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub staking_token_owner: Signer<'info>,
+    #[account(
+        constraint = staking_account.owner_staking_token_account == *owner_staking_token_account.to_account_info().key,
+    )]
+    pub owner_staking_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = staking_account.staking_token_owner == *staking_token_owner.key,
+        constraint = staking_account.owner_staking_token_account == *owner_staking_token_account.to_account_info().key,
+        constraint = staking_account.staking_mint == *staking_mint.to_account_info().key,
+        has_one = pool,
+    )]
+    pub staking_account: Box<Account<'info, StakeAccount>>,
+    pub staking_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [USER_SUMMARY_PDA_SEED, pool.key().as_ref(), staking_token_owner.key().as_ref()],
+        bump,
+    )]
+    pub user_stake_summary: Box<Account<'info, UserStakeSummary>>,
+    #[account(mut, has_one = reward_mint)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    #[account(mut)]
+    pub reward_mint: Account<'info, Mint>,
+    /// CHECK: this is safe because it is a PDA bound to this program, and is only used as a token account when the pool pays rewards from its vault
+    #[account(
+        mut,
+        seeds = [REWARD_VAULT_PDA_SEED, pool.key().as_ref(), reward_mint.key().as_ref()],
+        bump,
+    )]
+    pub reward_vault: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = staking_account.owner_reward_token_account == *owner_reward_token_account.to_account_info().key,
+    )]
+    pub owner_reward_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CollectMany<'info> {
+    /// CHECK: this is safe because it is a PDA bound to this program
+    #[account(
+        mut,
+        seeds = [MINT_AUTHORITY_PDA_SEED, pool.key().as_ref(), reward_mint.key().as_ref()],
+        bump,
+    )]
+    pub reward_mint_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub staking_token_owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [USER_SUMMARY_PDA_SEED, pool.key().as_ref(), staking_token_owner.key().as_ref()],
+        bump,
+    )]
+    pub user_stake_summary: Box<Account<'info, UserStakeSummary>>,
+    #[account(mut, has_one = reward_mint)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    #[account(mut)]
+    pub reward_mint: Account<'info, Mint>,
+    /// CHECK: this is safe because it is a PDA bound to this program, and is only used as a token account when the pool pays rewards from its vault
+    #[account(
+        mut,
+        seeds = [REWARD_VAULT_PDA_SEED, pool.key().as_ref(), reward_mint.key().as_ref()],
+        bump,
+    )]
+    pub reward_vault: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = owner_reward_token_account.owner == *staking_token_owner.key,
+        constraint = owner_reward_token_account.mint == *reward_mint.to_account_info().key,
+    )]
+    pub owner_reward_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CollectFull<'info> {
+    /// CHECK: this is safe because it is a PDA bound to this program
+    #[account(
+        mut,
+        seeds = [MINT_AUTHORITY_PDA_SEED, pool.key().as_ref(), reward_mint.key().as_ref()],
+        bump,
+    )]
+    pub reward_mint_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub staking_token_owner: Signer<'info>,
+    #[account(
+        constraint = staking_account.owner_staking_token_account == *owner_staking_token_account.to_account_info().key,
+    )]
+    pub owner_staking_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = staking_account.staking_token_owner == *staking_token_owner.key,
+        constraint = staking_account.owner_staking_token_account == *owner_staking_token_account.to_account_info().key,
+        constraint = staking_account.staking_mint == *staking_mint.to_account_info().key,
+        has_one = pool,
+    )]
+    pub staking_account: Box<Account<'info, StakeAccount>>,
+    pub staking_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [USER_SUMMARY_PDA_SEED, pool.key().as_ref(), staking_token_owner.key().as_ref()],
+        bump,
+    )]
+    pub user_stake_summary: Box<Account<'info, UserStakeSummary>>,
+    #[account(mut, has_one = reward_mint)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    #[account(mut)]
+    pub reward_mint: Account<'info, Mint>,
+    /// CHECK: this is safe because it is a PDA bound to this program, and is only used as a token account when the pool pays rewards from its vault
+    #[account(
+        mut,
+        seeds = [REWARD_VAULT_PDA_SEED, pool.key().as_ref(), reward_mint.key().as_ref()],
+        bump,
+    )]
+    pub reward_vault: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = staking_account.owner_reward_token_account == *owner_reward_token_account.to_account_info().key,
+    )]
+    pub owner_reward_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct Unstake<'info> {
+    #[account(mut)] 
+    pub staking_token_owner: Signer<'info>,
+    pub staking_mint: Account<'info, Mint>, 
+    #[account(
+        mut,
+        seeds = [STAKING_ACCOUNT_PDA_SEED, staking_account.key().as_ref(), staking_mint.key().as_ref()],
+        bump
+    )]
+    pub vault_account: Account<'info, TokenAccount>,
+    /// CHECK: this is safe because it is calculated by the client
+    #[account(
+        seeds = [VAULT_PDA_SEED, staking_account.key().as_ref(), staking_mint.key().as_ref()],
+        bump
+    )]
+    pub vault_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub owner_staking_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = staking_account.staking_token_owner == *staking_token_owner.key,
+        constraint = staking_account.owner_staking_token_account == *owner_staking_token_account.to_account_info().key,
+        constraint = staking_account.staking_mint == *staking_mint.to_account_info().key,
+        has_one = pool,
+        close = staking_token_owner
+    )]
+    pub staking_account: Box<Account<'info, StakeAccount>>,
+    #[account(
+        mut,
+        seeds = [USER_SUMMARY_PDA_SEED, pool.key().as_ref(), staking_token_owner.key().as_ref()],
+        bump,
+    )]
+    pub user_stake_summary: Box<Account<'info, UserStakeSummary>>,
+    #[account(mut)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> Unstake<'info> {
+    fn into_transfer_to_initializer_context(
+        &self,
+    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.vault_account.to_account_info().clone(),
+            to: self
+            .owner_staking_token_account
+            .to_account_info()
+            .clone(),
+            authority: self.vault_authority.clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+    
+    fn into_close_context(&self) -> CpiContext<'_, '_, '_, 'info, CloseAccount<'info>> {
+        let cpi_accounts = CloseAccount {
+            account: self.vault_account.to_account_info().clone(),
+            destination: self.staking_token_owner.to_account_info().clone(),
+            authority: self.vault_authority.clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+}
+
+#[derive(Accounts)]
+pub struct ClaimAndUnstake<'info> {
+    /// CHECK: this is safe because it is a PDA bound to this program
+    #[account(
+        mut,
+        seeds = [MINT_AUTHORITY_PDA_SEED, pool.key().as_ref(), reward_mint.key().as_ref()],
+        bump,
+    )]
+    pub reward_mint_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub staking_token_owner: Signer<'info>,
+    pub staking_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [STAKING_ACCOUNT_PDA_SEED, staking_account.key().as_ref(), staking_mint.key().as_ref()],
+        bump
+    )]
+    pub vault_account: Account<'info, TokenAccount>,
+    /// CHECK: this is safe because it is calculated by the client
+    #[account(
+        seeds = [VAULT_PDA_SEED, staking_account.key().as_ref(), staking_mint.key().as_ref()],
+        bump
+    )]
+    pub vault_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub owner_staking_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = staking_account.staking_token_owner == *staking_token_owner.key,
+        constraint = staking_account.owner_staking_token_account == *owner_staking_token_account.to_account_info().key,
+        constraint = staking_account.staking_mint == *staking_mint.to_account_info().key,
+        has_one = pool,
+        close = staking_token_owner
+    )]
+    pub staking_account: Box<Account<'info, StakeAccount>>,
+    #[account(
+        mut,
+        seeds = [USER_SUMMARY_PDA_SEED, pool.key().as_ref(), staking_token_owner.key().as_ref()],
+        bump,
+    )]
+    pub user_stake_summary: Box<Account<'info, UserStakeSummary>>,
+    #[account(mut, has_one = reward_mint)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    #[account(mut)]
+    pub reward_mint: Account<'info, Mint>,
+    /// CHECK: this is safe because it is a PDA bound to this program, and is only used as a token account when the pool pays rewards from its vault
+    #[account(
+        mut,
+        seeds = [REWARD_VAULT_PDA_SEED, pool.key().as_ref(), reward_mint.key().as_ref()],
+        bump,
+    )]
+    pub reward_vault: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = staking_account.owner_reward_token_account == *owner_reward_token_account.to_account_info().key,
+    )]
+    pub owner_reward_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> ClaimAndUnstake<'info> {
+    fn into_transfer_to_initializer_context(
+        &self,
+    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.vault_account.to_account_info().clone(),
+            to: self.owner_staking_token_account.to_account_info().clone(),
+            authority: self.vault_authority.clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+
+    fn into_close_context(&self) -> CpiContext<'_, '_, '_, 'info, CloseAccount<'info>> {
+        let cpi_accounts = CloseAccount {
+            account: self.vault_account.to_account_info().clone(),
+            destination: self.staking_token_owner.to_account_info().clone(),
+            authority: self.vault_authority.clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+}
+
+#[derive(Accounts)]
+pub struct EarlyUnstake<'info> {
+    #[account(mut)]
+    pub staking_token_owner: Signer<'info>,
+    pub staking_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [STAKING_ACCOUNT_PDA_SEED, staking_account.key().as_ref(), staking_mint.key().as_ref()],
+        bump
+    )]
+    pub vault_account: Account<'info, TokenAccount>,
+    /// CHECK: this is safe because it is calculated by the client
+    #[account(
+        seeds = [VAULT_PDA_SEED, staking_account.key().as_ref(), staking_mint.key().as_ref()],
+        bump
+    )]
+    pub vault_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub owner_staking_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = staking_account.staking_token_owner == *staking_token_owner.key,
+        constraint = staking_account.owner_staking_token_account == *owner_staking_token_account.to_account_info().key,
+        constraint = staking_account.staking_mint == *staking_mint.to_account_info().key,
+        has_one = pool,
+        close = staking_token_owner
+    )]
+    pub staking_account: Box<Account<'info, StakeAccount>>,
+    #[account(
+        mut,
+        seeds = [USER_SUMMARY_PDA_SEED, pool.key().as_ref(), staking_token_owner.key().as_ref()],
+        bump,
+    )]
+    pub user_stake_summary: Box<Account<'info, UserStakeSummary>>,
+    #[account(mut)]
+    pub pool: Box<Account<'info, StakingPool>>,
+    #[account(
+        mut,
+        constraint = staking_account.owner_reward_token_account == *owner_reward_token_account.to_account_info().key,
+    )]
+    pub owner_reward_token_account: Account<'info, TokenAccount>,
+    /// CHECK: this is safe because it is checked against the pool's treasury before any penalty is paid
+    #[account(mut)]
+    pub treasury: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> EarlyUnstake<'info> {
+    fn into_transfer_penalty_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.owner_reward_token_account.to_account_info().clone(),
+            to: self.treasury.clone(),
+            authority: self.staking_token_owner.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+
+    fn into_transfer_to_initializer_context(
+        &self,
+    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.vault_account.to_account_info().clone(),
+            to: self.owner_staking_token_account.to_account_info().clone(),
+            authority: self.vault_authority.clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+
+    fn into_close_context(&self) -> CpiContext<'_, '_, '_, 'info, CloseAccount<'info>> {
+        let cpi_accounts = CloseAccount {
+            account: self.vault_account.to_account_info().clone(),
+            destination: self.staking_token_owner.to_account_info().clone(),
+            authority: self.vault_authority.clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+}
+
+#[account]
+pub struct StakeAccount {
+    pub pool: Pubkey,
+    pub staking_token_owner: Pubkey,
+    pub owner_staking_token_account: Pubkey,
+    pub staking_mint: Pubkey,
+    pub created: i64,
+    pub unstake_date: i64,
+    pub lock_days: u16,
+    pub is_one_of_one: bool,
+    pub rarity_tier: u8,
+    pub full_reward_collected: bool,
+    pub last_reward_collection: i64,
+    pub total_reward_collected: i64,
+    pub owner_reward_token_account: Pubkey,
+    pub auto_renew: bool,
+    pub reward_remainder: u128,
+    pub weight: u64,
+    pub reward_debt: u128,
+    pub stream_rewards: Vec<StreamReward>,
+    pub revenue_shares: Vec<RevenueShare>,
+}
+
+impl StakeAccount {
+    const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // pool
+        + PUBLIC_KEY_LENGTH // owner
+        + PUBLIC_KEY_LENGTH // owner_staking_token_account
+        + PUBLIC_KEY_LENGTH // owner_reward_token_account
+        + PUBLIC_KEY_LENGTH // mint
+        + TIMESTAMP_LENGTH // created
+        + TIMESTAMP_LENGTH // last_reward_collection
+        + 1 // total_reward_collected
+        + TIMESTAMP_LENGTH // unstake_date
+        + 32 // staking period
+        + 1 // is one of one
+        + 1 // rarity tier
+        + 8 // reward collected
+        + 1 // auto_renew
+        + 16 // reward_remainder
+        + 8 // weight
+        + 16 // reward_debt
+        + 4 + StreamReward::LEN * MAX_REWARD_STREAMS // stream_rewards
+        + 4 + RevenueShare::LEN * MAX_REVENUE_TOKENS; // revenue_shares
+
+    fn is_flex(&self) -> bool {
+        self.lock_days == FLEX_LOCK_DAYS
+    }
+
+    // Reward due since the last collection, and the fraction of a reward token left over (scaled by REWARD_PRECISION).
+    // Rewards accrue per second at the stake's fixed-point rate over the time elapsed since the last collection, up to the end of the lock,
+    // and the fraction left over from the previous collection is carried forward. The total collected never exceeds the full reward of the lock.
+    fn reward_due(&self, pool: &StakingPool, timestamp: i64) -> Result<(i64, u128)> {
+
+        // In shared emission pools the stake earns its share of the pool's emissions, whatever its lock
+        if pool.mode == PoolMode::Shared {
+            let pending: u128 = self.shared_pending(pool)?;
+            let amount = i64::try_from(pending / REWARD_PRECISION).map_err(|_| ErrorCode::MathOverflow)?;
+
+            return Ok((amount, pending % REWARD_PRECISION))
+        }
+
+        let rate: u128 = self.reward_rate(pool)?;
+
+        // Flex stakes earn until they are unstaked, with no cap
+        if self.is_flex() {
+            let accrued = pool.scheduled_reward(rate, self.last_reward_collection, timestamp, self.reward_remainder)?;
+            let amount = i64::try_from(accrued / REWARD_PRECISION).map_err(|_| ErrorCode::MathOverflow)?;
+
+            return Ok((amount, accrued % REWARD_PRECISION))
+        }
+
+        // Define the full_amount based on the lock length, rarity tier and emission schedule. This also checks that the rarity tier is valid.
+        let full_amount: i64 = self.full_reward(pool)?;
+
+        // Check that the reward has not already been fully collected
+        if self.total_reward_collected >= full_amount {
+            return Err(ErrorCode::FullRewardAlreadyCollected.into())
+        }
+
+        // Accrue over the seconds of the lock that have passed since staking/last collection, at the rate of the emission schedule
+        let accrued = pool.scheduled_reward(rate, self.last_reward_collection, timestamp.min(self.unstake_date), self.reward_remainder)?;
+
+        // Catch cases that might results in the staking_token_owner collecting more than the full_amount
+        let amount = i64::try_from(accrued / REWARD_PRECISION).map_err(|_| ErrorCode::MathOverflow)?;
+        let amount = amount.min(full_amount - self.total_reward_collected);
+
+        Ok((amount, accrued % REWARD_PRECISION))
+    }
+
+    // Share of a shared emission pool's emissions earned since the last collection, plus what was carried from the last collection,
+    // scaled by REWARD_PRECISION. The pool's emissions must be brought up to date first.
+    fn shared_pending(&self, pool: &StakingPool) -> Result<u128> {
+        (self.weight as u128)
+            .checked_mul(pool.acc_reward_per_share)
+            .and_then(|accumulated| accumulated.checked_sub(self.reward_debt))
+            .and_then(|pending| pending.checked_add(self.reward_remainder))
+            .ok_or_else(|| ErrorCode::MathOverflow.into())
+    }
+
+    // Full reward of the lock: the pool's full reward for the lock length and rarity tier, scaled by the average multiplier
+    // of the emission schedule over the lock and rounded down
+    fn full_reward(&self, pool: &StakingPool) -> Result<i64> {
+        let full_amount: i64 = pool.full_reward(self.lock_days, self.rarity_tier as usize)?;
+        let duration = (self.unstake_date - self.created) as u128;
+
+        let scheduled = (full_amount as u128)
+            .checked_mul(pool.scheduled_bps_seconds(self.created, self.unstake_date)?)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        i64::try_from(scheduled / (BASIS_POINTS as u128 * duration)).map_err(|_| ErrorCode::MathOverflow.into())
+    }
+
+    // Reward earned per second at the full rate of the emission schedule, scaled by REWARD_PRECISION:
+    // the pool's full reward for the lock spread over the lock, or the pool's flex rate for flex stakes
+    fn reward_rate(&self, pool: &StakingPool) -> Result<u128> {
+
+        if self.is_flex() {
+            return pool.flex_reward_rate(self.rarity_tier as usize)
+        }
+
+        let full_amount: i64 = pool.full_reward(self.lock_days, self.rarity_tier as usize)?;
+        let duration = (self.unstake_date - self.created) as u128;
+
+        // Rounded down; the rounding is paid when the rest of the full reward is issued at the end of the lock
+        let rate = (full_amount as u128).checked_mul(REWARD_PRECISION).ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(rate / duration)
+    }
+
+    // Full reward of the lock that has not been collected yet, or for flex and shared emission stakes the reward accrued since the last collection,
+    // and the fraction of a reward token left over (scaled by REWARD_PRECISION). The full reward of a lock leaves no fraction over.
+    fn remaining_reward(&self, pool: &StakingPool, timestamp: i64) -> Result<(i64, u128)> {
+
+        if self.is_flex() || pool.mode == PoolMode::Shared {
+            return self.reward_due(pool, timestamp)
+        }
+
+        let full_amount: i64 = self.full_reward(pool)?;
+
+        Ok(((full_amount - self.total_reward_collected).max(0), 0))
+    }
+
+    // Start a new lock of the same length and rarity tier, with the reward counters reset and the fraction of a reward token
+    // left over from the finished lock carried forward
+    fn renew(&mut self, pool: &StakingPool, timestamp: i64, remainder: u128) -> ProgramResult {
+        let duration = pool.lock_duration(self.lock_days)?;
+
+        // Keep what the reward streams earned in the finished lock for the next collection, and start them again from now
+        self.accrue_streams(pool, timestamp)?;
+        for stream_reward in self.stream_rewards.iter_mut() {
+            stream_reward.last_update = timestamp;
+        }
+
+        self.created = timestamp;
+        self.unstake_date = timestamp + duration;
+        self.last_reward_collection = timestamp;
+        self.total_reward_collected = 0;
+        self.reward_remainder = remainder;
+        self.reward_debt = (self.weight as u128).checked_mul(pool.acc_reward_per_share).ok_or(ErrorCode::MathOverflow)?;
+        self.full_reward_collected = false;
+
+        Ok(())
+    }
+
+    // Bring the stake's reward streams up to date: what each stream has earned since its last update, up to the end of the lock, is added
+    // to its pending amount. Streams added to the pool after the token was staked start earning when they were added.
+    fn accrue_streams(&mut self, pool: &StakingPool, timestamp: i64) -> ProgramResult {
+        let end = if self.is_flex() { timestamp } else { timestamp.min(self.unstake_date) };
+        let weight = pool.stake_weight(self.lock_days, self.rarity_tier as usize)?;
+
+        for (i, stream) in pool.reward_streams.iter().enumerate() {
+            if i == self.stream_rewards.len() {
+                self.stream_rewards.push(StreamReward {
+                    collected: 0,
+                    pending: 0,
+                    last_update: stream.start_time.max(self.created),
+                });
+            }
+
+            let stream_reward = &mut self.stream_rewards[i];
+            let elapsed = (end - stream_reward.last_update).max(0) as u128;
+
+            stream_reward.pending = accrue(pool.stream_reward_rate(stream, weight)?, elapsed, stream_reward.pending)?;
+            stream_reward.last_update = stream_reward.last_update.max(end);
+        }
+
+        Ok(())
+    }
+
+    // The stake's share of one of the pool's revenue tokens. Tokens registered after the token was staked start from nothing.
+    fn revenue_share(&mut self, index: usize) -> &mut RevenueShare {
+        while self.revenue_shares.len() <= index {
+            self.revenue_shares.push(RevenueShare { debt: 0, claimed: 0 });
+        }
+
+        &mut self.revenue_shares[index]
+    }
+
+    // Update the total amount rewarded for the staked token, the time of the last collection and the fraction carried forward,
+    // and mark the reward as fully collected once the full amount of the lock has been issued
+    fn record_collection(&mut self, pool: &StakingPool, amount: i64, remainder: u128, timestamp: i64) -> ProgramResult {
+        self.last_reward_collection = timestamp;
+        self.total_reward_collected = self.total_reward_collected.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+
+        self.reward_remainder = remainder;
+
+        // In shared emission pools everything the stake has earned so far has now been paid or carried
+        if pool.mode == PoolMode::Shared {
+            self.reward_debt = (self.weight as u128).checked_mul(pool.acc_reward_per_share).ok_or(ErrorCode::MathOverflow)?;
+
+            return Ok(())
+        }
+
+        // Flex stakes have no full reward
+        if self.is_flex() {
+            return Ok(())
+        }
+
+        let full_amount: i64 = self.full_reward(pool)?;
+
+        if self.total_reward_collected >= full_amount {
+            self.full_reward_collected = true
+        }
+
+        Ok(())
+    }
+}
+
+// Pay everything the stake's reward streams have earned, in whole tokens, with the fraction left over carried to the next payment.
+// The accounts of every stream of the pool are passed through stream_accounts, as groups of REWARD_STREAM_GROUP_LEN accounts in the pool's stream order.
+fn pay_reward_streams<'info>(
+    program_id: &Pubkey,
+    pool: &Account<'info, StakingPool>,
+    staking_account: &mut StakeAccount,
+    stream_accounts: &[AccountInfo<'info>],
+    token_program: &AccountInfo<'info>,
+    timestamp: i64,
+) -> ProgramResult {
+
+    if stream_accounts.len() != pool.reward_streams.len() * REWARD_STREAM_GROUP_LEN {
+        return Err(ErrorCode::InvalidRewardStreamAccounts.into())
+    }
+
+    staking_account.accrue_streams(pool, timestamp)?;
+
+    for (i, (stream, group)) in pool.reward_streams.iter().zip(stream_accounts.chunks(REWARD_STREAM_GROUP_LEN)).enumerate() {
+        let stream_mint = &group[0];
+        let stream_mint_authority = &group[1];
+        let stream_vault = &group[2];
+        let owner_stream_token_account = &group[3];
+
+        // Check the stream's mint and derived addresses, and that the tokens are paid to the owner of the stake
+        let (stream_mint_authority_address, _stream_mint_authority_bump) =
+            Pubkey::find_program_address(&[MINT_AUTHORITY_PDA_SEED, pool.key().as_ref(), stream.mint.as_ref()], program_id);
+        let (stream_vault_address, _stream_vault_bump) =
+            Pubkey::find_program_address(&[REWARD_VAULT_PDA_SEED, pool.key().as_ref(), stream.mint.as_ref()], program_id);
+
+        let token_account = Account::<TokenAccount>::try_from(owner_stream_token_account)?;
+
+        if *stream_mint.key != stream.mint
+            || *stream_mint_authority.key != stream_mint_authority_address
+            || *stream_vault.key != stream_vault_address
+            || token_account.mint != stream.mint
+            || token_account.owner != staking_account.staking_token_owner
+        {
+            return Err(ErrorCode::InvalidRewardStreamAccounts.into())
+        }
+
+        let stream_reward = &mut staking_account.stream_rewards[i];
+        let amount = u64::try_from(stream_reward.pending / REWARD_PRECISION).map_err(|_| ErrorCode::MathOverflow)?;
+
+        if amount > 0 {
+            pay_reward(
+                program_id,
+                &pool.key(),
+                stream.source,
+                stream_mint,
+                stream_mint_authority,
+                stream_vault,
+                owner_stream_token_account,
+                token_program,
+                amount,
+            )?;
+        }
+
+        stream_reward.pending %= REWARD_PRECISION;
+        stream_reward.collected = stream_reward.collected.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+    }
+
+    Ok(())
+}
+
+// Pay a reward of the given mint to the staker's token account. Rewards are minted through the mint's authority (PDA) for the pool,
+// or transferred from the pool's vault (PDA) for that mint, which the same PDA owns, for vaults funded through fund_pool.
+#[allow(clippy::too_many_arguments)]
+fn pay_reward<'info>(
+    program_id: &Pubkey,
+    pool: &Pubkey,
+    source: RewardSource,
+    reward_mint: &AccountInfo<'info>,
+    reward_mint_authority: &AccountInfo<'info>,
+    reward_vault: &AccountInfo<'info>,
+    owner_reward_token_account: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    amount: u64,
+) -> ProgramResult {
+
+    // Find the PDA/bump and set the signature
+    let (_mint_authority, mint_authority_bump) =
+        Pubkey::find_program_address(&[MINT_AUTHORITY_PDA_SEED, pool.as_ref(), reward_mint.key.as_ref()], program_id);
+
+    let seeds = &[MINT_AUTHORITY_PDA_SEED, pool.as_ref(), reward_mint.key.as_ref(), &[mint_authority_bump]];
+    let authority_seeds = [&seeds[..]];
+
+    match source {
+        RewardSource::Mint => {
+            let cpi_accounts = MintTo {
+                mint: reward_mint.clone(),
+                to: owner_reward_token_account.clone(),
+                authority: reward_mint_authority.clone(),
+            };
+            token::mint_to(CpiContext::new_with_signer(token_program.clone(), cpi_accounts, &authority_seeds), amount)
+        }
+        RewardSource::Vault => {
+            // Check that the vault still holds enough to pay the reward
+            if Account::<TokenAccount>::try_from(reward_vault)?.amount < amount {
+                return Err(ErrorCode::RewardVaultEmpty.into())
+            }
+
+            let cpi_accounts = Transfer {
+                from: reward_vault.clone(),
+                to: owner_reward_token_account.clone(),
+                authority: reward_mint_authority.clone(),
+            };
+            token::transfer(CpiContext::new_with_signer(token_program.clone(), cpi_accounts, &authority_seeds), amount)
+        }
+    }
+}
+
+// Create an account at a PDA with space bytes, owned by owner, with the payer funding its rent. create_account fails for an address
+// that already holds lamports, and anyone can send lamports to a PDA, so like Anchor's init such an address is topped up to
+// its rent-exempt balance, then allocated and assigned instead.
+fn create_pda_account<'info>(
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    rent: &Rent,
+    space: usize,
+    owner: &Pubkey,
+    seeds: &[&[u8]],
+) -> ProgramResult {
+    let lamports = rent.minimum_balance(space);
+
+    if account.lamports() == 0 {
+        return invoke_signed(
+            &system_instruction::create_account(payer.key, account.key, lamports, space as u64, owner),
+            &[payer.clone(), account.clone(), system_program.clone()],
+            &[seeds],
+        )
+    }
+
+    let top_up = lamports.saturating_sub(account.lamports());
+    if top_up > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, top_up),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+
+    invoke_signed(&system_instruction::allocate(account.key, space as u64), &[account.clone(), system_program.clone()], &[seeds])?;
+    invoke_signed(&system_instruction::assign(account.key, owner), &[account.clone(), system_program.clone()], &[seeds])
+}
+
+// Check the reward settings shared by initialize_pool and update_pool: no reward is negative, the standard rarity tier exists,
+// and the reward curve has at least one breakpoint, in increasing order of lock length
+fn check_reward_config(base_reward_per_day: i64, reward_curve: &[CurvePoint], rarity_tiers: &[RarityTier], minimum_collection_period: i64) -> ProgramResult {
+    if minimum_collection_period <= 0
+        || base_reward_per_day < 0
+        || rarity_tiers.is_empty()
+        || rarity_tiers.len() > MAX_RARITY_TIERS
+        || reward_curve.is_empty()
+        || reward_curve.len() > MAX_CURVE_POINTS
+        || reward_curve.windows(2).any(|points| points[0].lock_days >= points[1].lock_days)
+    {
+        return Err(ErrorCode::InvalidPoolConfig.into())
+    }
+
+    Ok(())
+}
+
+// Reward left unpaid by the pool's emission budget, added to the fraction carried to the next collection (scaled by REWARD_PRECISION)
+fn carry_unpaid(unpaid: i64, remainder: u128) -> Result<u128> {
+    (unpaid as u128)
+        .checked_mul(REWARD_PRECISION)
+        .and_then(|unpaid| unpaid.checked_add(remainder))
+        .ok_or_else(|| ErrorCode::MathOverflow.into())
+}
+
+// value * numerator / denominator rounded down, without the full product: the whole multiples of the denominator in value
+// are scaled by the numerator first, then what is left over
+fn mul_div(value: u128, numerator: u128, denominator: u128) -> Result<u128> {
+    let whole = (value / denominator).checked_mul(numerator);
+    let part = (value % denominator).checked_mul(numerator).map(|part| part / denominator);
+
+    whole.zip(part)
+        .and_then(|(whole, part)| whole.checked_add(part))
+        .ok_or_else(|| ErrorCode::MathOverflow.into())
+}
+
+// Reward accrued at a fixed-point rate over a number of seconds, plus the fraction carried from the previous collection
+fn accrue(rate: u128, elapsed: u128, remainder: u128) -> Result<u128> {
+    rate.checked_mul(elapsed)
+        .and_then(|accrued| accrued.checked_add(remainder))
+        .ok_or_else(|| ErrorCode::MathOverflow.into())
+}
+
+// Per-wallet totals for a pool, kept up to date as the owner stakes, collects and unstakes
+#[account]
+pub struct UserStakeSummary {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub active_stakes: u32,
+    pub total_reward_claimed: u64,
+    pub first_stake_time: i64,
+    pub staked_seconds: i64,
+    pub last_update: i64,
+}
+
+impl UserStakeSummary {
+    const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // pool
+        + PUBLIC_KEY_LENGTH // owner
+        + 4 // active_stakes
+        + 8 // total_reward_claimed
+        + TIMESTAMP_LENGTH // first_stake_time
+        + 8 // staked_seconds
+        + TIMESTAMP_LENGTH; // last_update
+
+    // Add the time each active stake has spent staked since the last update to staked_seconds
+    fn update(&mut self, timestamp: i64) -> ProgramResult {
+        let elapsed: i64 = (self.active_stakes as i64).checked_mul(timestamp - self.last_update).ok_or(ErrorCode::MathOverflow)?;
+        self.staked_seconds = self.staked_seconds.checked_add(elapsed).ok_or(ErrorCode::MathOverflow)?;
+        self.last_update = timestamp;
+
+        Ok(())
+    }
+
+    fn add_stake(&mut self, pool: Pubkey, owner: Pubkey, timestamp: i64) -> ProgramResult {
+        // A new summary starts with the owner's first stake in the pool
+        if self.first_stake_time == 0 {
+            self.pool = pool;
+            self.owner = owner;
+            self.first_stake_time = timestamp;
+            self.last_update = timestamp;
+        }
+
+        self.update(timestamp)?;
+        self.active_stakes = self.active_stakes.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(())
+    }
+
+    fn remove_stake(&mut self, timestamp: i64) -> ProgramResult {
+        self.update(timestamp)?;
+        self.active_stakes = self.active_stakes.checked_sub(1).ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(())
+    }
+
+    fn record_reward(&mut self, amount: u64) -> ProgramResult {
+        self.total_reward_claimed = self.total_reward_claimed.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(())
+    }
+}
+
+#[account]
+pub struct RarityRecord {
+    pub pool: Pubkey,
+    pub mint: Pubkey,
+    pub rarity_tier: u8,
+}
+
+impl RarityRecord {
+    const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // pool
+        + PUBLIC_KEY_LENGTH // mint
+        + 1; // rarity_tier
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct RarityTier {
+    pub multiplier_bps: u32,
+    pub is_one_of_one: bool,
+}
+
+impl RarityTier {
+    const LEN: usize = 4 // multiplier_bps
+        + 1; // is_one_of_one
+}
+
+// How a pool pays rewards: a fixed full reward per stake, or a fixed emission rate shared between all staked weight
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum PoolMode {
+    Fixed,
+    Shared,
+}
+
+// A reward token paid on top of the pool's reward mint: reward_per_day whole tokens per day, scaled by the stake's rarity tier and lock-length multipliers
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct RewardStream {
+    pub mint: Pubkey,
+    pub reward_per_day: u64,
+    pub decimals: u8,
+    pub source: RewardSource,
+    pub start_time: i64,
+}
+
+impl RewardStream {
+    const LEN: usize = PUBLIC_KEY_LENGTH // mint
+        + 8 // reward_per_day
+        + 1 // decimals
+        + 1 // source
+        + TIMESTAMP_LENGTH; // start_time
+}
+
+// What a stake has earned from one of the pool's reward streams: the total paid, and what has accrued since (scaled by REWARD_PRECISION)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct StreamReward {
+    pub collected: u64,
+    pub pending: u128,
+    pub last_update: i64,
+}
+
+impl StreamReward {
+    const LEN: usize = 8 // collected
+        + 16 // pending
+        + TIMESTAMP_LENGTH; // last_update
+}
+
+// A token whose revenue is shared between the pool's staked tokens. Each deposit is spread over a distribution period at revenue_rate
+// base units per second, and acc_revenue_per_token is the revenue shared per staked token so far. Both are scaled by REWARD_PRECISION.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct RevenueToken {
+    pub mint: Pubkey,
+    pub acc_revenue_per_token: u128,
+    pub revenue_rate: u128,
+    pub distribution_end: i64,
+    pub last_update: i64,
+    pub total_deposited: u64,
+}
+
+impl RevenueToken {
+    const LEN: usize = PUBLIC_KEY_LENGTH // mint
+        + 16 // acc_revenue_per_token
+        + 16 // revenue_rate
+        + TIMESTAMP_LENGTH // distribution_end
+        + TIMESTAMP_LENGTH // last_update
+        + 8; // total_deposited
+
+    // Bring the revenue shared per staked token up to date. Nothing is shared while no token is staked;
+    // the rest of the distribution period is pushed back instead.
+    fn update(&mut self, total_staked: u64, timestamp: i64) -> ProgramResult {
+        if self.last_update < self.distribution_end {
+            if total_staked == 0 {
+                self.distribution_end = timestamp + (self.distribution_end - self.last_update);
+            } else {
+                let elapsed = (timestamp.min(self.distribution_end) - self.last_update) as u128;
+                let shared = self.revenue_rate.checked_mul(elapsed).ok_or(ErrorCode::MathOverflow)?;
+
+                self.acc_revenue_per_token = self.acc_revenue_per_token
+                    .checked_add(shared / total_staked as u128)
+                    .ok_or(ErrorCode::MathOverflow)?;
+            }
+        }
+
+        self.last_update = timestamp;
+
+        Ok(())
+    }
+
+    // Spread an amount (scaled by REWARD_PRECISION), along with what is left of the current distribution, over a new distribution period
+    fn distribute(&mut self, amount: u128, duration: i64, timestamp: i64) -> ProgramResult {
+        let left = if timestamp < self.distribution_end {
+            self.revenue_rate.checked_mul((self.distribution_end - timestamp) as u128).ok_or(ErrorCode::MathOverflow)?
+        } else {
+            0
+        };
+
+        self.revenue_rate = amount.checked_add(left).ok_or(ErrorCode::MathOverflow)? / duration as u128;
+        self.distribution_end = timestamp + duration;
+
+        Ok(())
+    }
+}
+
+// A stake's share of one of the pool's revenue tokens: the revenue per staked token it is not owed (scaled by REWARD_PRECISION), and the total claimed
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct RevenueShare {
+    pub debt: u128,
+    pub claimed: u64,
+}
+
+impl RevenueShare {
+    const LEN: usize = 16 // debt
+        + 8; // claimed
+}
+
+// Where a pool's rewards come from: minted through the mint authority PDA, or transferred from the pool's reward vault
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum RewardSource {
+    Mint,
+    Vault,
+}
+
+// How the early unstake penalty of a pool is paid
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum PenaltyKind {
+    None,
+    RewardToken,
+    Sol,
+}
+
+// A breakpoint of the lock-length reward curve: locking for lock_days days multiplies the base reward by multiplier_bps.
+// Lock lengths between two breakpoints are interpolated linearly, and lock lengths outside the curve use the nearest breakpoint.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct CurvePoint {
+    pub lock_days: u16,
+    pub multiplier_bps: u32,
+}
+
+impl CurvePoint {
+    const LEN: usize = 2 // lock_days
+        + 4; // multiplier_bps
+}
+
+// A step of the emission schedule: from start_time until the next step, rewards accrue at multiplier_bps of the full rate
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub struct EmissionStep {
+    pub start_time: i64,
+    pub multiplier_bps: u32,
+}
+
+impl EmissionStep {
+    const LEN: usize = TIMESTAMP_LENGTH // start_time
+        + 4; // multiplier_bps
+}
+
+#[account]
+pub struct StakingPool {
+    pub collection: Pubkey,
+    pub creators: Vec<Pubkey>,
+    pub admin: Pubkey,
+    pub reward_mint: Pubkey,
+    pub min_lock_days: u16,
+    pub max_lock_days: u16,
+    pub base_reward_per_day: i64,
+    pub reward_decimals: u8,
+    pub reward_curve: Vec<CurvePoint>,
+    pub rarity_tiers: Vec<RarityTier>,
+    pub minimum_collection_period: i64,
+    pub day_length: i64,
+    pub treasury: Pubkey,
+    pub early_unstake_penalty_kind: PenaltyKind,
+    pub early_unstake_base_penalty: u64,
+    pub early_unstake_penalty_per_day: u64,
+    pub flex_reward_rate: i64,
+    pub flex_cooldown: i64,
+    pub mode: PoolMode,
+    pub emission_per_day: u64,
+    pub acc_reward_per_share: u128,
+    pub total_staked_weight: u64,
+    pub last_emission_update: i64,
+    pub reward_source: RewardSource,
+    pub pending_mint_authority: Option<Pubkey>,
+    pub mint_authority_release_time: i64,
+    pub reward_streams: Vec<RewardStream>,
+    pub revenue_tokens: Vec<RevenueToken>,
+    pub total_staked: u64,
+    pub one_of_one_staked: u64,
+    pub flex_staked: u64,
+    pub lock_tier_stakes: Vec<u64>,
+    pub total_rewards_paid: u64,
+    pub unique_stakers: u64,
+    pub emission_cap: u64,
+    pub epoch_budget: u64,
+    pub epoch_length: i64,
+    pub epoch_start: i64,
+    pub epoch_paid: u64,
+    pub emission_schedule: Vec<EmissionStep>,
+}
+
+impl StakingPool {
+    const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // collection
+        + 4 + PUBLIC_KEY_LENGTH * MAX_POOL_CREATORS // creators
+        + PUBLIC_KEY_LENGTH // admin
+        + PUBLIC_KEY_LENGTH // reward_mint
+        + 2 // min_lock_days
+        + 2 // max_lock_days
+        + 8 // base_reward_per_day
+        + 1 // reward_decimals
+        + 4 + CurvePoint::LEN * MAX_CURVE_POINTS // reward_curve
+        + 4 + RarityTier::LEN * MAX_RARITY_TIERS // rarity_tiers
+        + TIMESTAMP_LENGTH // minimum_collection_period
+        + TIMESTAMP_LENGTH // day_length
+        + PUBLIC_KEY_LENGTH // treasury
+        + 1 // early_unstake_penalty_kind
+        + 8 // early_unstake_base_penalty
+        + 8 // early_unstake_penalty_per_day
+        + 8 // flex_reward_rate
+        + TIMESTAMP_LENGTH // flex_cooldown
+        + 1 // mode
+        + 8 // emission_per_day
+        + 16 // acc_reward_per_share
+        + 8 // total_staked_weight
+        + TIMESTAMP_LENGTH // last_emission_update
+        + 1 // reward_source
+        + 1 + PUBLIC_KEY_LENGTH // pending_mint_authority
+        + TIMESTAMP_LENGTH // mint_authority_release_time
+        + 4 + RewardStream::LEN * MAX_REWARD_STREAMS // reward_streams
+        + 4 + RevenueToken::LEN * MAX_REVENUE_TOKENS // revenue_tokens
+        + 8 // total_staked
+        + 8 // one_of_one_staked
+        + 8 // flex_staked
+        + 4 + 8 * MAX_CURVE_POINTS // lock_tier_stakes
+        + 8 // total_rewards_paid
+        + 8 // unique_stakers
+        + 8 // emission_cap
+        + 8 // epoch_budget
+        + TIMESTAMP_LENGTH // epoch_length
+        + TIMESTAMP_LENGTH // epoch_start
+        + 8 // epoch_paid
+        + 4 + EmissionStep::LEN * MAX_EMISSION_STEPS; // emission_schedule
+
+    // Bring a shared emission pool's reward per share up to date: the emissions since the last update, in base units scaled by REWARD_PRECISION,
+    // divided between all staked weight and rounded down. Nothing is emitted while no weight is staked.
+    // The daily emission is divided before it is scaled by the time elapsed, so that a long gap between updates cannot overflow.
+    fn update_emission(&mut self, timestamp: i64) -> ProgramResult {
+        if self.mode != PoolMode::Shared || timestamp <= self.last_emission_update {
+            return Ok(())
+        }
+
+        if self.total_staked_weight > 0 {
+            let emission_per_day = (self.emission_per_day as u128)
+                .checked_mul(self.reward_unit())
+                .and_then(|emission| emission.checked_mul(REWARD_PRECISION))
+                .ok_or(ErrorCode::MathOverflow)?;
+
+            let emitted_per_share = mul_div(
+                emission_per_day,
+                self.scheduled_bps_seconds(self.last_emission_update, timestamp)?,
+                BASIS_POINTS as u128 * self.day_length as u128 * self.total_staked_weight as u128,
+            )?;
+
+            self.acc_reward_per_share = self.acc_reward_per_share
+                .checked_add(emitted_per_share)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
+
+        self.last_emission_update = timestamp;
+
+        Ok(())
+    }
+
+    // Multiplier of the emission schedule in basis points, summed over each second from start to end
+    fn scheduled_bps_seconds(&self, start: i64, end: i64) -> Result<u128> {
+        if end <= start {
+            return Ok(0)
+        }
+
+        let mut total: u128 = 0;
+        let mut time = start;
+        let mut multiplier_bps = BASIS_POINTS as u128;
+
+        for step in self.emission_schedule.iter() {
+            if step.start_time >= end {
+                break
+            }
+
+            if step.start_time > time {
+                total = total
+                    .checked_add(multiplier_bps * (step.start_time - time) as u128)
+                    .ok_or(ErrorCode::MathOverflow)?;
+                time = step.start_time;
+            }
+
+            multiplier_bps = step.multiplier_bps as u128;
+        }
+
+        total.checked_add(multiplier_bps * (end - time) as u128).ok_or_else(|| ErrorCode::MathOverflow.into())
+    }
+
+    // Reward accrued from start to end at a fixed-point rate scaled by the emission schedule, rounded down,
+    // plus the fraction carried from the previous collection
+    fn scheduled_reward(&self, rate: u128, start: i64, end: i64, remainder: u128) -> Result<u128> {
+        rate.checked_mul(self.scheduled_bps_seconds(start, end)?)
+            .map(|accrued| accrued / BASIS_POINTS as u128)
+            .and_then(|accrued| accrued.checked_add(remainder))
+            .ok_or_else(|| ErrorCode::MathOverflow.into())
+    }
+
+    // Weight of a stake in a shared emission pool: its rarity tier multiplier scaled by the reward curve for its lock length
+    fn stake_weight(&self, lock_days: u16, rarity_tier: usize) -> Result<u64> {
+        let tier = self.rarity_tiers.get(rarity_tier).ok_or(ErrorCode::InvalidRarityTier)?;
+
+        Ok(tier.multiplier_bps as u64 * self.curve_multiplier(lock_days) as u64 / BASIS_POINTS as u64)
+    }
+
+    // Start a stake sharing a shared emission pool's emissions from now on. Stakes in fixed reward pools have no weight.
+    fn add_stake_weight(&mut self, stake: &mut StakeAccount) -> ProgramResult {
+        if self.mode != PoolMode::Shared {
+            return Ok(())
+        }
+
+        stake.weight = self.stake_weight(stake.lock_days, stake.rarity_tier as usize)?;
+        stake.reward_debt = (stake.weight as u128).checked_mul(self.acc_reward_per_share).ok_or(ErrorCode::MathOverflow)?;
+        self.total_staked_weight = self.total_staked_weight.checked_add(stake.weight).ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(())
+    }
+
+    // Stop a stake sharing a shared emission pool's emissions. Anything it has not been paid yet is no longer tracked.
+    fn remove_stake_weight(&mut self, stake: &mut StakeAccount) -> ProgramResult {
+        self.total_staked_weight = self.total_staked_weight.saturating_sub(stake.weight);
+        stake.weight = 0;
+        stake.reward_debt = 0;
+
+        Ok(())
+    }
+
+    // Start a staked token sharing the pool's revenue from now on
+    fn add_staked_token(&mut self, stake: &mut StakeAccount, timestamp: i64) -> ProgramResult {
+        for revenue_token in self.revenue_tokens.iter_mut() {
+            revenue_token.update(self.total_staked, timestamp)?;
+        }
+
+        stake.revenue_shares = self.revenue_tokens
+            .iter()
+            .map(|revenue_token| RevenueShare { debt: revenue_token.acc_revenue_per_token, claimed: 0 })
+            .collect();
+        self.total_staked = self.total_staked.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+
+        if stake.is_one_of_one {
+            self.one_of_one_staked = self.one_of_one_staked.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        }
+        let count = self.lock_tier_count(stake.lock_days)?;
+        *count = count.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(())
+    }
+
+    // Stop an unstaked token sharing the pool's revenue. Fails while the token has whole units of revenue left to claim,
+    // so that claim_revenue is called first. Only the fraction of a unit left after claiming is shared again between the remaining staked tokens.
+    fn remove_staked_token(&mut self, stake: &StakeAccount, timestamp: i64) -> ProgramResult {
+        let duration = REVENUE_DISTRIBUTION_DAYS * self.day_length;
+        let total_staked = self.total_staked;
+        self.total_staked = total_staked.saturating_sub(1);
+
+        if stake.is_one_of_one {
+            self.one_of_one_staked = self.one_of_one_staked.saturating_sub(1);
+        }
+        let count = self.lock_tier_count(stake.lock_days)?;
+        *count = count.saturating_sub(1);
+
+        for (i, revenue_token) in self.revenue_tokens.iter_mut().enumerate() {
+            revenue_token.update(total_staked, timestamp)?;
+
+            let debt = stake.revenue_shares.get(i).map_or(0, |share| share.debt);
+            let unclaimed = revenue_token.acc_revenue_per_token.saturating_sub(debt);
+
+            if unclaimed >= REWARD_PRECISION {
+                return Err(ErrorCode::UnclaimedRevenue.into())
+            }
+
+            if unclaimed > 0 {
+                revenue_token.distribute(unclaimed, duration, timestamp)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // The pool's count of staked tokens at a lock length: flex stakes, or the stakes counted under the longest reward curve breakpoint
+    // at or below the lock length. Locks shorter than the first breakpoint count under it.
+    fn lock_tier_count(&mut self, lock_days: u16) -> Result<&mut u64> {
+        if lock_days == FLEX_LOCK_DAYS {
+            return Ok(&mut self.flex_staked)
+        }
+
+        let tier = self.reward_curve.iter().rposition(|point| point.lock_days <= lock_days).unwrap_or(0);
+
+        self.lock_tier_stakes.get_mut(tier).ok_or_else(|| ErrorCode::InvalidPoolConfig.into())
+    }
+
+    // Move a staked token from the count of its old lock length to that of its new one
+    fn move_lock_tier(&mut self, old_lock_days: u16, new_lock_days: u16) -> ProgramResult {
+        let old_count = self.lock_tier_count(old_lock_days)?;
+        *old_count = old_count.saturating_sub(1);
+
+        let new_count = self.lock_tier_count(new_lock_days)?;
+        *new_count = new_count.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(())
+    }
+
+    fn add_unique_staker(&mut self) -> ProgramResult {
+        self.unique_stakers = self.unique_stakers.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(())
+    }
+
+    // Reward tokens the pool can still pay under its emission cap and the budget of the current epoch, in base units.
+    // Once the current epoch has ended, a new one starts at the last epoch boundary with its budget untouched.
+    fn emission_available(&mut self, timestamp: i64) -> u64 {
+        if self.epoch_budget > 0 && timestamp >= self.epoch_start + self.epoch_length {
+            self.epoch_start += (timestamp - self.epoch_start) / self.epoch_length * self.epoch_length;
+            self.epoch_paid = 0;
+        }
+
+        let mut available = u64::MAX;
+
+        if self.emission_cap > 0 {
+            available = self.emission_cap.saturating_sub(self.total_rewards_paid);
+        }
+
+        if self.epoch_budget > 0 {
+            available = available.min(self.epoch_budget.saturating_sub(self.epoch_paid));
+        }
+
+        available
+    }
+
+    // Take up to amount reward tokens from what the pool can still pay, and count them as paid. Returns the amount that can be paid.
+    fn take_emission(&mut self, amount: i64, timestamp: i64) -> Result<i64> {
+        let available = i64::try_from(self.emission_available(timestamp)).unwrap_or(i64::MAX);
+        let paid = amount.min(available).max(0);
+
+        self.record_reward_paid(paid as u64)?;
+
+        Ok(paid)
+    }
+
+    // Count reward tokens paid from the reward mint, whether minted or transferred from the reward vault
+    fn record_reward_paid(&mut self, amount: u64) -> ProgramResult {
+        self.total_rewards_paid = self.total_rewards_paid.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        self.epoch_paid = self.epoch_paid.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(())
+    }
+
+    // Number of seconds a token is locked for when staked for lock_days days. Flex stakes are only locked for the cooldown.
+    fn lock_duration(&self, lock_days: u16) -> Result<i64> {
+        if lock_days == FLEX_LOCK_DAYS {
+            if self.flex_reward_rate == 0 {
+                return Err(ErrorCode::InvalidStakingPeriod.into())
+            }
+
+            return Ok(self.flex_cooldown)
+        }
+
+        if lock_days < self.min_lock_days || lock_days > self.max_lock_days {
+            return Err(ErrorCode::InvalidStakingPeriod.into())
+        }
+
+        Ok(lock_days as i64 * self.day_length)
+    }
+
+    // Flex reward in base units earned per second, scaled by REWARD_PRECISION: the pool's daily flex rate scaled by the tier multiplier, rounded down
+    fn flex_reward_rate(&self, rarity_tier: usize) -> Result<u128> {
+        let tier = self.rarity_tiers.get(rarity_tier).ok_or(ErrorCode::InvalidRarityTier)?;
+
+        let rate = (self.flex_reward_rate as u128)
+            .checked_mul(tier.multiplier_bps as u128)
+            .and_then(|rate| rate.checked_mul(REWARD_PRECISION))
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(rate / (BASIS_POINTS as u128 * self.day_length as u128))
+    }
+
+    // Reward of a stream in base units earned per second by a stake of the given weight, scaled by REWARD_PRECISION and rounded down
+    fn stream_reward_rate(&self, stream: &RewardStream, weight: u64) -> Result<u128> {
+        let rate = (stream.reward_per_day as u128)
+            .checked_mul(10u128.pow(stream.decimals as u32))
+            .and_then(|rate| rate.checked_mul(weight as u128))
+            .and_then(|rate| rate.checked_mul(REWARD_PRECISION))
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        Ok(rate / (BASIS_POINTS as u128 * self.day_length as u128))
+    }
+
+    // Penalty charged for leaving a lock early with the given number of seconds left, in base units of the reward token or lamports depending on the penalty kind
+    fn early_unstake_penalty(&self, time_left: i64) -> Result<u64> {
+        if self.early_unstake_penalty_kind == PenaltyKind::None {
+            return Ok(0)
+        }
+
+        // Count every started day of the lock that is left
+        let days_left = (time_left + self.day_length - 1) / self.day_length;
+
+        self.early_unstake_penalty_per_day
+            .checked_mul(days_left as u64)
+            .and_then(|penalty| penalty.checked_add(self.early_unstake_base_penalty))
+            .ok_or_else(|| ErrorCode::MathOverflow.into())
+    }
+
+    // Lock-length multiplier of the reward curve, interpolated linearly between the breakpoints around lock_days
+    fn curve_multiplier(&self, lock_days: u16) -> i64 {
+        let first = self.reward_curve[0];
+        let last = self.reward_curve[self.reward_curve.len() - 1];
+
+        if lock_days <= first.lock_days {
+            return first.multiplier_bps as i64
+        }
+
+        if lock_days >= last.lock_days {
+            return last.multiplier_bps as i64
+        }
+
+        let i = self.reward_curve.iter().position(|point| point.lock_days >= lock_days).unwrap();
+        let (low, high) = (self.reward_curve[i - 1], self.reward_curve[i]);
+
+        let span = (high.lock_days - low.lock_days) as i64;
+        let offset = (lock_days - low.lock_days) as i64;
+
+        low.multiplier_bps as i64 + (high.multiplier_bps as i64 - low.multiplier_bps as i64) * offset / span
+    }
+
+    // Number of base units in one whole reward token
+    fn reward_unit(&self) -> u128 {
+        10u128.pow(self.reward_decimals as u32)
+    }
+
+    // Full reward in base units for a lock length and rarity tier: the linear base reward for the lock, scaled by the curve multiplier
+    // and the tier multiplier. The product is rounded down once, at the end.
+    fn full_reward(&self, lock_days: u16, rarity_tier: usize) -> Result<i64> {
+        let tier = self.rarity_tiers.get(rarity_tier).ok_or(ErrorCode::InvalidRarityTier)?;
+
+        let amount = (self.base_reward_per_day as u128)
+            .checked_mul(lock_days as u128)
+            .and_then(|amount| amount.checked_mul(self.curve_multiplier(lock_days) as u128))
+            .and_then(|amount| amount.checked_mul(tier.multiplier_bps as u128))
+            .ok_or(ErrorCode::MathOverflow)?
+            / (BASIS_POINTS as u128 * BASIS_POINTS as u128);
+
+        Ok(i64::try_from(amount).map_err(|_| ErrorCode::MathOverflow)?)
+    }
+
+    // Rarity tier of a mint, from its rarity record if the admin has created one, or the standard tier otherwise
+    fn rarity_tier(&self, program_id: &Pubkey, rarity_record: &AccountInfo) -> Result<u8> {
+        let rarity_tier: u8 = if rarity_record.owner == program_id && !rarity_record.data_is_empty() {
+            Account::<RarityRecord>::try_from(rarity_record)?.rarity_tier
+        } else {
+            0
+        };
+
+        if rarity_tier as usize >= self.rarity_tiers.len() {
+            return Err(ErrorCode::InvalidRarityTier.into())
+        }
+
+        Ok(rarity_tier)
+    }
+
+    // Check that the Token Metadata account belongs to the staking mint, and that the token is a member of the pool's collection
+    fn check_collection_member(&self, staking_mint: &Pubkey, staking_mint_metadata: &AccountInfo) -> ProgramResult {
+        let metadata = Metadata::from_account_info(staking_mint_metadata)?;
+        if metadata.mint != *staking_mint || !self.is_collection_member(&metadata) {
+            return Err(ErrorCode::NotInCollection.into())
+        }
+
+        Ok(())
+    }
+
+    // A token is a member if its metadata has the pool's verified collection, or a verified creator listed on the pool
+    fn is_collection_member(&self, metadata: &Metadata) -> bool {
+        if let Some(collection) = &metadata.collection {
+            if collection.verified && collection.key == self.collection {
+                return true
+            }
+        }
+
+        if let Some(creators) = &metadata.data.creators {
+            return creators
+                .iter()
+                .any(|creator| creator.verified && self.creators.contains(&creator.address))
+        }
+
+        false
+    }
 }
 
 #[error]
@@ -577,5 +3260,39 @@ pub enum ErrorCode {
     #[msg("The reward has already been collected for this staking period.")]
     FullRewardAlreadyCollected,
     #[msg("The staking period is not valid.")]
-    InvalidStakingPeriod
+    InvalidStakingPeriod,
+    #[msg("The pool configuration is not valid.")]
+    InvalidPoolConfig,
+    #[msg("The token is not a verified member of this pool's collection.")]
+    NotInCollection,
+    #[msg("The rarity tier is not valid.")]
+    InvalidRarityTier,
+    #[msg("The accounts passed for batch staking are not valid.")]
+    InvalidStakeManyAccounts,
+    #[msg("The accounts passed for batch collection are not valid.")]
+    InvalidCollectManyAccounts,
+    #[msg("The staking period has ended, unstake the token instead.")]
+    StakingPeriodEnded,
+    #[msg("The treasury account does not match the pool's treasury.")]
+    InvalidTreasury,
+    #[msg("Auto-renew is not enabled for this stake.")]
+    AutoRenewDisabled,
+    #[msg("The reward calculation overflowed.")]
+    MathOverflow,
+    #[msg("The pool's reward vault does not hold enough tokens to pay this reward.")]
+    RewardVaultEmpty,
+    #[msg("The funding amount must be greater than zero.")]
+    InvalidFundingAmount,
+    #[msg("No release of the reward mint authority has been requested.")]
+    MintAuthorityReleaseNotRequested,
+    #[msg("The reward mint authority cannot be released until the timelock has passed.")]
+    MintAuthorityReleaseLocked,
+    #[msg("The accounts passed for the pool's reward streams are not valid.")]
+    InvalidRewardStreamAccounts,
+    #[msg("The token is not one of the pool's revenue tokens.")]
+    InvalidRevenueToken,
+    #[msg("The pool's emission budget has been used up.")]
+    EmissionBudgetExhausted,
+    #[msg("The stake has revenue to claim before it can be unstaked.")]
+    UnclaimedRevenue
 }
\ No newline at end of file
@@ -0,0 +1,10 @@
+#!/usr/bin/env bash
+# Dump the programs that Anchor.toml's [[test.genesis]] loads into the local test validator, unless they are already there
+set -euo pipefail
+
+cd "$(dirname "$0")/.."
+mkdir -p tests/deps
+
+if [ ! -f tests/deps/mpl_token_metadata.so ]; then
+  solana program dump -u m metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s tests/deps/mpl_token_metadata.so
+fi
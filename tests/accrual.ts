@@ -0,0 +1,138 @@
+import * as anchor from '@project-serum/anchor';
+import { Program } from '@project-serum/anchor';
+import { Staking } from '../target/types/staking';
+import { Keypair, PublicKey } from '@solana/web3.js';
+import { TOKEN_PROGRAM_ID, getAccount } from '@solana/spl-token';
+import { assert } from 'chai';
+
+import { ownerWalletKeypair, payerKeypair } from './utils/users';
+import { mintNft } from './utils/nft';
+import { setupPool, stakeToken, waitForClock } from './utils/pool';
+
+// Fixed-point scale of reward rates and of the fraction carried between collections (REWARD_PRECISION)
+const REWARD_PRECISION = new anchor.BN('1000000000000');
+
+// Runs against a local validator with the Token Metadata program loaded from tests/deps (see Anchor.toml)
+describe('per-second accrual', () => {
+  const provider = anchor.Provider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Staking as Program<Staking>;
+
+  const creatorKeypair = Keypair.generate();
+
+  let rewardMint: PublicKey;
+  let rewardTokenAccount: PublicKey;
+  let poolPda: PublicKey;
+  let userStakeSummaryPda: PublicKey;
+  let rewardMintAuthorityPda: PublicKey;
+  let rewardVaultPda: PublicKey;
+
+  before(async () => {
+    // 10 reward tokens per 30 second day, so a third of a token per second
+    ({
+      pool: poolPda,
+      rewardMint,
+      rewardTokenAccount,
+      rewardMintAuthority: rewardMintAuthorityPda,
+      rewardVault: rewardVaultPda,
+      userStakeSummary: userStakeSummaryPda,
+    } = await setupPool(provider, program, creatorKeypair.publicKey, {
+      baseRewardPerDay: 10,
+      dayLength: 30,
+    }));
+  });
+
+  it('Carries the fraction of a token between collections and pays it later', async () => {
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      creatorKeypair,
+      true
+    );
+
+    const { stakingAccount, vaultAccount, vaultAuthority } = await stakeToken(
+      program,
+      poolPda,
+      nft
+    );
+
+    const collectAccounts = {
+      rewardMintAuthority: rewardMintAuthorityPda,
+      stakingTokenOwner: ownerWalletKeypair.publicKey,
+      ownerStakingTokenAccount: nft.tokenAccount,
+      stakingAccount,
+      userStakeSummary: userStakeSummaryPda,
+      stakingMint: nft.mint,
+      pool: poolPda,
+      rewardMint,
+      rewardVault: rewardVaultPda,
+      ownerRewardTokenAccount: rewardTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+
+    const { created, unstakeDate } = await program.account.stakeAccount.fetch(
+      stakingAccount
+    );
+
+    // The full reward of 10 spread over the 30 second lock, rounded down
+    const rate = new anchor.BN(10).mul(REWARD_PRECISION).divn(30);
+
+    // Collect twice during the lock, each time once the minimum collection period of 10 seconds has passed
+    let lastRewardCollection = created;
+
+    for (let i = 0; i < 2; i++) {
+      await waitForClock(provider.connection, lastRewardCollection.addn(10));
+
+      await program.rpc.collect({
+        accounts: collectAccounts,
+        signers: [ownerWalletKeypair],
+      });
+
+      const stake = await program.account.stakeAccount.fetch(stakingAccount);
+      lastRewardCollection = stake.lastRewardCollection;
+
+      // What has been paid and carried adds up to what has accrued since staking, as if nothing was collected along the way
+      const accrued = rate.mul(lastRewardCollection.sub(created));
+
+      assert.equal(
+        stake.totalRewardCollected.toString(),
+        accrued.div(REWARD_PRECISION).toString()
+      );
+      assert.equal(
+        stake.rewardRemainder.toString(),
+        accrued.mod(REWARD_PRECISION).toString()
+      );
+
+      const rewards = await getAccount(provider.connection, rewardTokenAccount);
+      assert.equal(rewards.amount.toString(), stake.totalRewardCollected.toString());
+    }
+
+    // Wait for the one day lock to end
+    await waitForClock(provider.connection, unstakeDate);
+
+    await program.rpc.claimAndUnstake({
+      accounts: {
+        rewardMintAuthority: rewardMintAuthorityPda,
+        stakingTokenOwner: ownerWalletKeypair.publicKey,
+        stakingMint: nft.mint,
+        vaultAccount,
+        vaultAuthority,
+        ownerStakingTokenAccount: nft.tokenAccount,
+        stakingAccount,
+        userStakeSummary: userStakeSummaryPda,
+        pool: poolPda,
+        rewardMint,
+        rewardVault: rewardVaultPda,
+        ownerRewardTokenAccount: rewardTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      signers: [ownerWalletKeypair],
+    });
+
+    // No fraction of the full reward is lost to the collections along the way
+    const rewards = await getAccount(provider.connection, rewardTokenAccount);
+    assert.equal(Number(rewards.amount), 10);
+  });
+});
@@ -0,0 +1,281 @@
+import * as anchor from '@project-serum/anchor';
+import { Program } from '@project-serum/anchor';
+import { Staking } from '../target/types/staking';
+import { Keypair, PublicKey } from '@solana/web3.js';
+import { TOKEN_PROGRAM_ID, getAccount } from '@solana/spl-token';
+import { assert } from 'chai';
+
+import { ownerWalletKeypair, payerKeypair } from './utils/users';
+import { mintNft } from './utils/nft';
+import { setupPool, stakeToken, waitForClock } from './utils/pool';
+
+// Runs against a local validator with the Token Metadata program loaded from tests/deps (see Anchor.toml)
+describe('emission budget', () => {
+  const provider = anchor.Provider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Staking as Program<Staking>;
+
+  const creatorKeypair = Keypair.generate();
+
+  let rewardMint: PublicKey;
+  let rewardTokenAccount: PublicKey;
+  let poolPda: PublicKey;
+  let userStakeSummaryPda: PublicKey;
+  let rewardMintAuthorityPda: PublicKey;
+  let rewardVaultPda: PublicKey;
+
+  const setEmissionBudget = async (
+    emissionCap: number,
+    epochBudget = 0,
+    epochDays = 0
+  ) => {
+    await program.rpc.setEmissionBudget(
+      new anchor.BN(emissionCap),
+      new anchor.BN(epochBudget),
+      epochDays,
+      {
+        accounts: {
+          pool: poolPda,
+          admin: ownerWalletKeypair.publicKey,
+        },
+        signers: [ownerWalletKeypair],
+      }
+    );
+  };
+
+  before(async () => {
+    // 5 reward tokens per 10 second day
+    ({
+      pool: poolPda,
+      rewardMint,
+      rewardTokenAccount,
+      rewardMintAuthority: rewardMintAuthorityPda,
+      rewardVault: rewardVaultPda,
+      userStakeSummary: userStakeSummaryPda,
+    } = await setupPool(provider, program, creatorKeypair.publicKey));
+  });
+
+  it('Caps the emissions of a pool', async () => {
+    await setEmissionBudget(3);
+
+    const pool = await program.account.stakingPool.fetch(poolPda);
+    assert.equal(pool.emissionCap.toNumber(), 3);
+  });
+
+  it('Pays what the cap allows, and forfeits the rest to unstake', async () => {
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      creatorKeypair,
+      true
+    );
+
+    const { stakingAccount, vaultAccount, vaultAuthority } = await stakeToken(
+      program,
+      poolPda,
+      nft
+    );
+
+    const collectAccounts = {
+      rewardMintAuthority: rewardMintAuthorityPda,
+      stakingTokenOwner: ownerWalletKeypair.publicKey,
+      ownerStakingTokenAccount: nft.tokenAccount,
+      stakingAccount,
+      userStakeSummary: userStakeSummaryPda,
+      stakingMint: nft.mint,
+      pool: poolPda,
+      rewardMint,
+      rewardVault: rewardVaultPda,
+      ownerRewardTokenAccount: rewardTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+
+    // Wait for the one day lock to end. The full reward of 5 is more than the cap of 3.
+    const { unstakeDate } = await program.account.stakeAccount.fetch(
+      stakingAccount
+    );
+    await waitForClock(provider.connection, unstakeDate);
+
+    await program.rpc.collect({
+      accounts: collectAccounts,
+      signers: [ownerWalletKeypair],
+    });
+
+    let rewards = await getAccount(provider.connection, rewardTokenAccount);
+    assert.equal(Number(rewards.amount), 3);
+
+    // Wait for the pool's minimum collection period to pass
+    const { lastRewardCollection } = await program.account.stakeAccount.fetch(
+      stakingAccount
+    );
+    await waitForClock(provider.connection, lastRewardCollection.addn(10));
+
+    try {
+      await program.rpc.collect({
+        accounts: collectAccounts,
+        signers: [ownerWalletKeypair],
+      });
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 6020);
+    }
+
+    const claimAccounts = {
+      rewardMintAuthority: rewardMintAuthorityPda,
+      stakingTokenOwner: ownerWalletKeypair.publicKey,
+      stakingMint: nft.mint,
+      vaultAccount,
+      vaultAuthority,
+      ownerStakingTokenAccount: nft.tokenAccount,
+      stakingAccount,
+      userStakeSummary: userStakeSummaryPda,
+      pool: poolPda,
+      rewardMint,
+      rewardVault: rewardVaultPda,
+      ownerRewardTokenAccount: rewardTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+
+    // The token is returned, and the 2 still owed are forfeited
+    await program.rpc.claimAndUnstake({
+      accounts: claimAccounts,
+      signers: [ownerWalletKeypair],
+    });
+
+    rewards = await getAccount(provider.connection, rewardTokenAccount);
+    assert.equal(Number(rewards.amount), 3);
+
+    const tokenAccount = await getAccount(provider.connection, nft.tokenAccount);
+    assert.equal(Number(tokenAccount.amount), 1);
+
+    const pool = await program.account.stakingPool.fetch(poolPda);
+    assert.equal(pool.totalRewardsPaid.toNumber(), 3);
+  });
+
+  it('Renews a stake once the cap is used up', async () => {
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      creatorKeypair,
+      true
+    );
+
+    const { stakingAccount } = await stakeToken(program, poolPda, nft);
+
+    await program.rpc.setAutoRenew(true, {
+      accounts: {
+        stakingTokenOwner: ownerWalletKeypair.publicKey,
+        stakingAccount,
+      },
+      signers: [ownerWalletKeypair],
+    });
+
+    const stake = await program.account.stakeAccount.fetch(stakingAccount);
+    await waitForClock(provider.connection, stake.unstakeDate);
+
+    // Nothing is left to pay the 5 owed, which are forfeited so that the crank keeps renewing
+    await program.rpc.renew({
+      accounts: {
+        rewardMintAuthority: rewardMintAuthorityPda,
+        cranker: payerKeypair.publicKey,
+        userStakeSummary: userStakeSummaryPda,
+        stakingAccount,
+        pool: poolPda,
+        rewardMint,
+        rewardVault: rewardVaultPda,
+        ownerRewardTokenAccount: rewardTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      signers: [payerKeypair],
+    });
+
+    const renewed = await program.account.stakeAccount.fetch(stakingAccount);
+    assert.isAbove(renewed.created.toNumber(), stake.created.toNumber());
+    assert.equal(renewed.totalRewardCollected.toNumber(), 0);
+
+    const pool = await program.account.stakingPool.fetch(poolPda);
+    assert.equal(pool.totalRewardsPaid.toNumber(), 3);
+  });
+
+  it('Pays the epoch budget again once the epoch has rolled over', async () => {
+    // No cap, and 2 reward tokens per epoch of three 10 second days
+    await setEmissionBudget(0, 2, 3);
+
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      creatorKeypair,
+      true
+    );
+
+    const { stakingAccount } = await stakeToken(program, poolPda, nft);
+
+    const collect = () =>
+      program.rpc.collect({
+        accounts: {
+          rewardMintAuthority: rewardMintAuthorityPda,
+          stakingTokenOwner: ownerWalletKeypair.publicKey,
+          ownerStakingTokenAccount: nft.tokenAccount,
+          stakingAccount,
+          userStakeSummary: userStakeSummaryPda,
+          stakingMint: nft.mint,
+          pool: poolPda,
+          rewardMint,
+          rewardVault: rewardVaultPda,
+          ownerRewardTokenAccount: rewardTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        },
+        signers: [ownerWalletKeypair],
+      });
+
+    const before = await getAccount(provider.connection, rewardTokenAccount);
+
+    // Wait for the one day lock to end. The epoch budget only covers 2 of the full reward of 5.
+    const { unstakeDate } = await program.account.stakeAccount.fetch(
+      stakingAccount
+    );
+    await waitForClock(provider.connection, unstakeDate);
+
+    await collect();
+
+    let rewards = await getAccount(provider.connection, rewardTokenAccount);
+    assert.equal(Number(rewards.amount - before.amount), 2);
+
+    const { epochStart, epochLength } = await program.account.stakingPool.fetch(
+      poolPda
+    );
+    assert.equal(epochLength.toNumber(), 30);
+
+    // The rest of the epoch pays nothing more
+    const { lastRewardCollection } = await program.account.stakeAccount.fetch(
+      stakingAccount
+    );
+    await waitForClock(provider.connection, lastRewardCollection.addn(10));
+
+    try {
+      await collect();
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 6020);
+    }
+
+    // The next epoch has a budget of its own
+    await waitForClock(provider.connection, epochStart.add(epochLength));
+
+    await collect();
+
+    rewards = await getAccount(provider.connection, rewardTokenAccount);
+    assert.equal(Number(rewards.amount - before.amount), 4);
+
+    const pool = await program.account.stakingPool.fetch(poolPda);
+    assert.equal(
+      pool.epochStart.toNumber(),
+      epochStart.add(epochLength).toNumber()
+    );
+    assert.equal(pool.epochPaid.toNumber(), 2);
+  });
+});
@@ -0,0 +1,627 @@
+import * as anchor from '@project-serum/anchor';
+import { Program } from '@project-serum/anchor';
+import { Staking } from '../target/types/staking';
+import { Keypair, LAMPORTS_PER_SOL, PublicKey } from '@solana/web3.js';
+import { TOKEN_PROGRAM_ID, getAccount } from '@solana/spl-token';
+import { assert } from 'chai';
+
+import { ownerWalletKeypair, payerKeypair } from './utils/users';
+import { mintNft } from './utils/nft';
+import {
+  findPoolPda,
+  findStakeAccounts,
+  setupPool,
+  stakeToken,
+  waitForClock,
+} from './utils/pool';
+
+// Runs against a local validator with the Token Metadata program loaded from tests/deps (see Anchor.toml)
+describe('collection verification', () => {
+  const provider = anchor.Provider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Staking as Program<Staking>;
+
+  const creatorKeypair = Keypair.generate();
+
+  let collectionKey: PublicKey;
+  let rewardMint: PublicKey;
+  let rewardTokenAccount: PublicKey;
+  let poolPda: PublicKey;
+  let userStakeSummaryPda: PublicKey;
+  let rewardMintAuthorityPda: PublicKey;
+  let rewardVaultPda: PublicKey;
+  const batchStakingAccounts: PublicKey[] = [];
+  let stakedNft;
+  let stakedNftStakingAccount: PublicKey;
+
+  before(async () => {
+    // 5 reward tokens per 10 second day, with 7, 14 and 28 day locks and a One-of-One tier
+    ({
+      collection: collectionKey,
+      pool: poolPda,
+      rewardMint,
+      rewardTokenAccount,
+      rewardMintAuthority: rewardMintAuthorityPda,
+      rewardVault: rewardVaultPda,
+      userStakeSummary: userStakeSummaryPda,
+    } = await setupPool(provider, program, creatorKeypair.publicKey, {
+      rewardCurve: [
+        { lockDays: 7, multiplierBps: 10000 },
+        { lockDays: 14, multiplierBps: 14000 },
+        { lockDays: 28, multiplierBps: 20000 },
+      ],
+      rarityTiers: [
+        { multiplierBps: 10000, isOneOfOne: false },
+        { multiplierBps: 15000, isOneOfOne: true },
+      ],
+    }));
+  });
+
+  it('Stakes a token with a verified pool creator', async () => {
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      creatorKeypair,
+      true
+    );
+
+    const { stakingAccount } = await stakeToken(program, poolPda, nft, 7);
+    stakedNft = nft;
+    stakedNftStakingAccount = stakingAccount;
+
+    const pool = await program.account.stakingPool.fetch(poolPda);
+    assert.ok(pool.collection.equals(collectionKey));
+    assert.ok(pool.creators[0].equals(creatorKeypair.publicKey));
+
+    const stake = await program.account.stakeAccount.fetch(stakingAccount);
+    assert.ok(stake.stakingMint.equals(nft.mint));
+    assert.ok(stake.pool.equals(poolPda));
+    assert.equal(stake.rarityTier, 0);
+    assert.ok(!stake.isOneOfOne);
+  });
+
+  it('Stakes a token at the rarity tier set by the admin', async () => {
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      creatorKeypair,
+      true
+    );
+
+    const rarityRecord = await findPoolPda(program, 'rarity', poolPda, nft.mint);
+
+    await program.rpc.setRarity(1, {
+      accounts: {
+        rarityRecord,
+        pool: poolPda,
+        mint: nft.mint,
+        admin: ownerWalletKeypair.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [ownerWalletKeypair],
+    });
+
+    const { stakingAccount } = await stakeToken(program, poolPda, nft, 7);
+
+    const stake = await program.account.stakeAccount.fetch(stakingAccount);
+    assert.equal(stake.rarityTier, 1);
+    assert.ok(stake.isOneOfOne);
+  });
+
+  it('Stakes several tokens in one transaction', async () => {
+    const remainingAccounts = [];
+    const stakingAccounts: PublicKey[] = [];
+
+    for (let i = 0; i < 2; i++) {
+      const nft = await mintNft(
+        provider.connection,
+        payerKeypair,
+        ownerWalletKeypair.publicKey,
+        creatorKeypair,
+        true
+      );
+
+      const { stakingAccount, vaultAccount, rarityRecord } =
+        await findStakeAccounts(program, poolPda, nft.mint);
+
+      remainingAccounts.push(
+        { pubkey: nft.mint, isWritable: false, isSigner: false },
+        { pubkey: nft.metadata, isWritable: false, isSigner: false },
+        { pubkey: rarityRecord, isWritable: false, isSigner: false },
+        { pubkey: nft.tokenAccount, isWritable: true, isSigner: false },
+        { pubkey: vaultAccount, isWritable: true, isSigner: false },
+        { pubkey: stakingAccount, isWritable: true, isSigner: false }
+      );
+      stakingAccounts.push(stakingAccount);
+    }
+
+    // Lamports sent to the derived addresses ahead of time do not stop them being created
+    const { vaultAccount } = await findStakeAccounts(
+      program,
+      poolPda,
+      remainingAccounts[0].pubkey
+    );
+    await provider.send(
+      new anchor.web3.Transaction().add(
+        ...[stakingAccounts[0], vaultAccount].map((toPubkey) =>
+          anchor.web3.SystemProgram.transfer({
+            fromPubkey: payerKeypair.publicKey,
+            toPubkey,
+            lamports: 1000,
+          })
+        )
+      ),
+      [payerKeypair]
+    );
+
+    await program.rpc.stakeMany(14, {
+      accounts: {
+        stakingTokenOwner: ownerWalletKeypair.publicKey,
+        userStakeSummary: userStakeSummaryPda,
+        ownerRewardTokenAccount: rewardTokenAccount,
+        pool: poolPda,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      remainingAccounts,
+      signers: [ownerWalletKeypair],
+    });
+
+    for (const stakingAccount of stakingAccounts) {
+      const stake = await program.account.stakeAccount.fetch(stakingAccount);
+      assert.equal(stake.lockDays, 14);
+      assert.ok(stake.pool.equals(poolPda));
+      batchStakingAccounts.push(stakingAccount);
+    }
+  });
+
+  it('Collects rewards for several stakes in one transaction', async () => {
+    // Wait for the pool's minimum collection period to pass
+    const { created } = await program.account.stakeAccount.fetch(
+      batchStakingAccounts[0]
+    );
+    await waitForClock(provider.connection, created.addn(10));
+
+    const before = await getAccount(provider.connection, rewardTokenAccount);
+
+    await program.rpc.collectMany({
+      accounts: {
+        rewardMintAuthority: rewardMintAuthorityPda,
+        stakingTokenOwner: ownerWalletKeypair.publicKey,
+        userStakeSummary: userStakeSummaryPda,
+        pool: poolPda,
+        rewardMint,
+        rewardVault: rewardVaultPda,
+        ownerRewardTokenAccount: rewardTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      remainingAccounts: batchStakingAccounts.map((pubkey) => ({
+        pubkey,
+        isWritable: true,
+        isSigner: false,
+      })),
+      signers: [ownerWalletKeypair],
+    });
+
+    const after = await getAccount(provider.connection, rewardTokenAccount);
+    assert.ok(after.amount > before.amount);
+
+    for (const stakingAccount of batchStakingAccounts) {
+      const stake = await program.account.stakeAccount.fetch(stakingAccount);
+      assert.ok(stake.totalRewardCollected.toNumber() > 0);
+    }
+  });
+
+  it('Claims the remaining reward and unstakes in one instruction', async () => {
+    const stake = await program.account.stakeAccount.fetch(
+      stakedNftStakingAccount
+    );
+
+    // Wait for the staking period to end
+    await waitForClock(provider.connection, stake.unstakeDate);
+
+    const { vaultAccount, vaultAuthority } = await findStakeAccounts(
+      program,
+      poolPda,
+      stakedNft.mint
+    );
+
+    const before = await getAccount(provider.connection, rewardTokenAccount);
+
+    await program.rpc.claimAndUnstake({
+      accounts: {
+        rewardMintAuthority: rewardMintAuthorityPda,
+        stakingTokenOwner: ownerWalletKeypair.publicKey,
+        stakingMint: stakedNft.mint,
+        vaultAccount,
+        vaultAuthority,
+        ownerStakingTokenAccount: stakedNft.tokenAccount,
+        stakingAccount: stakedNftStakingAccount,
+        userStakeSummary: userStakeSummaryPda,
+        pool: poolPda,
+        rewardMint,
+        rewardVault: rewardVaultPda,
+        ownerRewardTokenAccount: rewardTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      signers: [ownerWalletKeypair],
+    });
+
+    const after = await getAccount(provider.connection, rewardTokenAccount);
+    assert.equal(Number(after.amount - before.amount), 35);
+
+    const tokenAccount = await getAccount(
+      provider.connection,
+      stakedNft.tokenAccount
+    );
+    assert.equal(Number(tokenAccount.amount), 1);
+
+    assert.isNull(
+      await provider.connection.getAccountInfo(stakedNftStakingAccount)
+    );
+  });
+
+  it('Renews a stake with auto-renew once its staking period ends', async () => {
+    const stakingAccount = batchStakingAccounts[0];
+    const renewAccounts = {
+      rewardMintAuthority: rewardMintAuthorityPda,
+      cranker: payerKeypair.publicKey,
+      userStakeSummary: userStakeSummaryPda,
+      stakingAccount,
+      pool: poolPda,
+      rewardMint,
+      rewardVault: rewardVaultPda,
+      ownerRewardTokenAccount: rewardTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+
+    try {
+      await program.rpc.renew({
+        accounts: renewAccounts,
+        signers: [payerKeypair],
+      });
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 6012);
+    }
+
+    await program.rpc.setAutoRenew(true, {
+      accounts: {
+        stakingTokenOwner: ownerWalletKeypair.publicKey,
+        stakingAccount,
+      },
+      signers: [ownerWalletKeypair],
+    });
+
+    const stake = await program.account.stakeAccount.fetch(stakingAccount);
+
+    // Wait for the staking period to end
+    await waitForClock(provider.connection, stake.unstakeDate);
+
+    const before = await getAccount(provider.connection, rewardTokenAccount);
+
+    await program.rpc.renew({
+      accounts: renewAccounts,
+      signers: [payerKeypair],
+    });
+
+    const after = await getAccount(provider.connection, rewardTokenAccount);
+    assert.equal(
+      Number(after.amount - before.amount),
+      98 - stake.totalRewardCollected.toNumber()
+    );
+
+    const renewed = await program.account.stakeAccount.fetch(stakingAccount);
+    assert.equal(renewed.totalRewardCollected.toNumber(), 0);
+    assert.isFalse(renewed.fullRewardCollected);
+    assert.equal(
+      renewed.unstakeDate.toNumber(),
+      renewed.created.toNumber() + 140
+    );
+    assert.isAbove(renewed.created.toNumber(), stake.created.toNumber());
+  });
+
+  it('Extends a stake to a longer lock', async () => {
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      creatorKeypair,
+      true
+    );
+    const { stakingAccount } = await stakeToken(program, poolPda, nft, 7);
+
+    await program.rpc.extendStake(28, {
+      accounts: {
+        stakingTokenOwner: ownerWalletKeypair.publicKey,
+        stakingAccount,
+        pool: poolPda,
+      },
+      signers: [ownerWalletKeypair],
+    });
+
+    const stake = await program.account.stakeAccount.fetch(stakingAccount);
+    assert.equal(stake.lockDays, 28);
+    assert.equal(stake.unstakeDate.toNumber(), stake.created.toNumber() + 280);
+
+    // Moving back to a shorter lock is not allowed
+    try {
+      await program.rpc.extendStake(14, {
+        accounts: {
+          stakingTokenOwner: ownerWalletKeypair.publicKey,
+          stakingAccount,
+          pool: poolPda,
+        },
+        signers: [ownerWalletKeypair],
+      });
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 6004);
+    }
+  });
+
+  it('Unstakes early, forfeiting the reward and paying the penalty in SOL', async () => {
+    const treasury = Keypair.generate().publicKey;
+    const basePenalty = 0.01 * LAMPORTS_PER_SOL;
+    const penaltyPerDay = 0.001 * LAMPORTS_PER_SOL;
+
+    await program.rpc.setEarlyUnstakePenalty(
+      { sol: {} },
+      new anchor.BN(basePenalty),
+      new anchor.BN(penaltyPerDay),
+      {
+        accounts: {
+          pool: poolPda,
+          treasury,
+          admin: ownerWalletKeypair.publicKey,
+        },
+        signers: [ownerWalletKeypair],
+      }
+    );
+
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      creatorKeypair,
+      true
+    );
+    const { stakingAccount, vaultAccount, vaultAuthority } = await stakeToken(
+      program,
+      poolPda,
+      nft,
+      7
+    );
+
+    await program.rpc.earlyUnstake({
+      accounts: {
+        stakingTokenOwner: ownerWalletKeypair.publicKey,
+        stakingMint: nft.mint,
+        vaultAccount,
+        vaultAuthority,
+        ownerStakingTokenAccount: nft.tokenAccount,
+        stakingAccount,
+        userStakeSummary: userStakeSummaryPda,
+        pool: poolPda,
+        ownerRewardTokenAccount: rewardTokenAccount,
+        treasury,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      signers: [ownerWalletKeypair],
+    });
+
+    // The 7 day lock was left within its first day
+    assert.equal(
+      await provider.connection.getBalance(treasury),
+      basePenalty + 7 * penaltyPerDay
+    );
+
+    const tokenAccount = await getAccount(provider.connection, nft.tokenAccount);
+    assert.equal(Number(tokenAccount.amount), 1);
+
+    assert.isNull(await provider.connection.getAccountInfo(stakingAccount));
+  });
+
+  it('Stakes a token in flex mode and unstakes it after the cooldown', async () => {
+    const FLEX_LOCK_DAYS = 0;
+
+    // 10 reward tokens per second at the standard tier, with a 5 second cooldown
+    await program.rpc.setFlexMode(new anchor.BN(100), new anchor.BN(5), {
+      accounts: {
+        pool: poolPda,
+        admin: ownerWalletKeypair.publicKey,
+      },
+      signers: [ownerWalletKeypair],
+    });
+
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      creatorKeypair,
+      true
+    );
+    const { stakingAccount, vaultAccount, vaultAuthority } = await stakeToken(
+      program,
+      poolPda,
+      nft,
+      FLEX_LOCK_DAYS
+    );
+
+    const stake = await program.account.stakeAccount.fetch(stakingAccount);
+    assert.equal(stake.lockDays, FLEX_LOCK_DAYS);
+    assert.equal(stake.unstakeDate.toNumber(), stake.created.toNumber() + 5);
+
+    await waitForClock(provider.connection, stake.created.addn(11));
+
+    const before = await getAccount(provider.connection, rewardTokenAccount);
+
+    await program.rpc.collect({
+      accounts: {
+        rewardMintAuthority: rewardMintAuthorityPda,
+        stakingTokenOwner: ownerWalletKeypair.publicKey,
+        ownerStakingTokenAccount: nft.tokenAccount,
+        stakingAccount,
+        userStakeSummary: userStakeSummaryPda,
+        stakingMint: nft.mint,
+        pool: poolPda,
+        rewardMint,
+        rewardVault: rewardVaultPda,
+        ownerRewardTokenAccount: rewardTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      signers: [ownerWalletKeypair],
+    });
+
+    const after = await getAccount(provider.connection, rewardTokenAccount);
+    assert.isAtLeast(Number(after.amount - before.amount), 110);
+
+    await program.rpc.claimAndUnstake({
+      accounts: {
+        rewardMintAuthority: rewardMintAuthorityPda,
+        stakingTokenOwner: ownerWalletKeypair.publicKey,
+        stakingMint: nft.mint,
+        vaultAccount,
+        vaultAuthority,
+        ownerStakingTokenAccount: nft.tokenAccount,
+        stakingAccount,
+        userStakeSummary: userStakeSummaryPda,
+        pool: poolPda,
+        rewardMint,
+        rewardVault: rewardVaultPda,
+        ownerRewardTokenAccount: rewardTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      signers: [ownerWalletKeypair],
+    });
+
+    const tokenAccount = await getAccount(provider.connection, nft.tokenAccount);
+    assert.equal(Number(tokenAccount.amount), 1);
+  });
+
+  it("Keeps a summary of the owner's stakes in the pool", async () => {
+    const stakes = await program.account.stakeAccount.all([
+      { memcmp: { offset: 8, bytes: poolPda.toBase58() } },
+    ]);
+
+    const summary = await program.account.userStakeSummary.fetch(
+      userStakeSummaryPda
+    );
+    assert.ok(summary.owner.equals(ownerWalletKeypair.publicKey));
+    assert.equal(summary.activeStakes, stakes.length);
+    assert.isAbove(summary.firstStakeTime.toNumber(), 0);
+    assert.isAbove(summary.stakedSeconds.toNumber(), 0);
+
+    const rewards = await getAccount(provider.connection, rewardTokenAccount);
+    assert.equal(summary.totalRewardClaimed.toNumber(), Number(rewards.amount));
+  });
+
+  it('Keeps statistics of the pool', async () => {
+    const stakes = await program.account.stakeAccount.all([
+      { memcmp: { offset: 8, bytes: poolPda.toBase58() } },
+    ]);
+    const pool = await program.account.stakingPool.fetch(poolPda);
+
+    assert.equal(pool.totalStaked.toNumber(), stakes.length);
+    assert.equal(
+      pool.oneOfOneStaked.toNumber(),
+      stakes.filter((stake) => stake.account.isOneOfOne).length
+    );
+    assert.equal(pool.flexStaked.toNumber(), 0);
+
+    // Curve breakpoints are at 7, 14 and 28 days
+    for (const [tier, lockDays] of [7, 14, 28].entries()) {
+      assert.equal(
+        pool.lockTierStakes[tier].toNumber(),
+        stakes.filter((stake) => stake.account.lockDays === lockDays).length
+      );
+    }
+
+    const rewards = await getAccount(provider.connection, rewardTokenAccount);
+    assert.equal(pool.totalRewardsPaid.toNumber(), Number(rewards.amount));
+    assert.equal(pool.uniqueStakers.toNumber(), 1);
+  });
+
+  it('Updates the rewards of the pool', async () => {
+    const curve = [
+      { lockDays: 7, multiplierBps: 10000 },
+      { lockDays: 14, multiplierBps: 15000 },
+      { lockDays: 28, multiplierBps: 20000 },
+    ];
+    const tiers = [
+      { multiplierBps: 10000, isOneOfOne: false },
+      { multiplierBps: 15000, isOneOfOne: true },
+    ];
+
+    const updatePool = async (lockDays: number[]) => {
+      await program.rpc.updatePool(
+        new anchor.BN(10),
+        curve.map((point, i) => ({ ...point, lockDays: lockDays[i] })),
+        tiers,
+        new anchor.BN(20),
+        {
+          accounts: {
+            pool: poolPda,
+            admin: ownerWalletKeypair.publicKey,
+          },
+          signers: [ownerWalletKeypair],
+        }
+      );
+    };
+
+    // Breakpoints cannot move while tokens are staked
+    try {
+      await updatePool([7, 14, 30]);
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 6005);
+    }
+
+    await updatePool([7, 14, 28]);
+
+    const pool = await program.account.stakingPool.fetch(poolPda);
+    assert.equal(pool.baseRewardPerDay.toNumber(), 10);
+    assert.equal(pool.rewardCurve[1].multiplierBps, 15000);
+    assert.equal(pool.minimumCollectionPeriod.toNumber(), 20);
+  });
+
+  it('Fails to stake a token whose pool creator is not verified', async () => {
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      creatorKeypair,
+      false
+    );
+
+    try {
+      await stakeToken(program, poolPda, nft, 7);
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 6006);
+    }
+  });
+
+  it('Fails to stake a token from an unrelated creator', async () => {
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      Keypair.generate(),
+      true
+    );
+
+    try {
+      await stakeToken(program, poolPda, nft, 7);
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 6006);
+    }
+  });
+});
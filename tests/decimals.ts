@@ -0,0 +1,145 @@
+import * as anchor from '@project-serum/anchor';
+import { Program } from '@project-serum/anchor';
+import { Staking } from '../target/types/staking';
+import { Keypair, PublicKey } from '@solana/web3.js';
+import { TOKEN_PROGRAM_ID, getAccount } from '@solana/spl-token';
+import { assert } from 'chai';
+
+import { ownerWalletKeypair, payerKeypair } from './utils/users';
+import { mintNft } from './utils/nft';
+import { setupPool, stakeToken, waitForClock } from './utils/pool';
+
+// Runs against a local validator with the Token Metadata program loaded from tests/deps (see Anchor.toml)
+describe('reward mint with decimals', () => {
+  const provider = anchor.Provider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Staking as Program<Staking>;
+
+  const creatorKeypair = Keypair.generate();
+
+  let rewardMint: PublicKey;
+  let rewardTokenAccount: PublicKey;
+  let poolPda: PublicKey;
+  let userStakeSummaryPda: PublicKey;
+  let rewardMintAuthorityPda: PublicKey;
+  let rewardVaultPda: PublicKey;
+
+  // Accounts of claim_and_unstake and collect for a token staked with stakeToken
+  const rewardAccounts = (
+    nft: { mint: PublicKey; tokenAccount: PublicKey },
+    stake: { stakingAccount: PublicKey; vaultAccount: PublicKey; vaultAuthority: PublicKey }
+  ) => ({
+    rewardMintAuthority: rewardMintAuthorityPda,
+    stakingTokenOwner: ownerWalletKeypair.publicKey,
+    stakingMint: nft.mint,
+    vaultAccount: stake.vaultAccount,
+    vaultAuthority: stake.vaultAuthority,
+    ownerStakingTokenAccount: nft.tokenAccount,
+    stakingAccount: stake.stakingAccount,
+    userStakeSummary: userStakeSummaryPda,
+    pool: poolPda,
+    rewardMint,
+    rewardVault: rewardVaultPda,
+    ownerRewardTokenAccount: rewardTokenAccount,
+    tokenProgram: TOKEN_PROGRAM_ID,
+  });
+
+  before(async () => {
+    // 2.5 reward tokens of 6 decimals per 10 second day, given in base units
+    ({
+      pool: poolPda,
+      rewardMint,
+      rewardTokenAccount,
+      rewardMintAuthority: rewardMintAuthorityPda,
+      rewardVault: rewardVaultPda,
+      userStakeSummary: userStakeSummaryPda,
+    } = await setupPool(
+      provider,
+      program,
+      creatorKeypair.publicKey,
+      { baseRewardPerDay: 2_500_000 },
+      true,
+      6
+    ));
+  });
+
+  it('Pays a fractional daily reward in base units', async () => {
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      creatorKeypair,
+      true
+    );
+
+    const stake = await stakeToken(program, poolPda, nft);
+
+    // Wait for the one day lock to end
+    const { unstakeDate } = await program.account.stakeAccount.fetch(
+      stake.stakingAccount
+    );
+    await waitForClock(provider.connection, unstakeDate);
+
+    await program.rpc.claimAndUnstake({
+      accounts: rewardAccounts(nft, stake),
+      signers: [ownerWalletKeypair],
+    });
+
+    const rewards = await getAccount(provider.connection, rewardTokenAccount);
+    assert.equal(Number(rewards.amount), 2_500_000);
+  });
+
+  it('Pays a fractional flex rate in base units', async () => {
+    const FLEX_LOCK_DAYS = 0;
+
+    // 1.5 reward tokens per day, so 150,000 base units per second, with a 5 second cooldown
+    await program.rpc.setFlexMode(new anchor.BN(1_500_000), new anchor.BN(5), {
+      accounts: {
+        pool: poolPda,
+        admin: ownerWalletKeypair.publicKey,
+      },
+      signers: [ownerWalletKeypair],
+    });
+
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      creatorKeypair,
+      true
+    );
+
+    const stake = await stakeToken(program, poolPda, nft, FLEX_LOCK_DAYS);
+
+    const { created } = await program.account.stakeAccount.fetch(
+      stake.stakingAccount
+    );
+    await waitForClock(provider.connection, created.addn(10));
+
+    const before = await getAccount(provider.connection, rewardTokenAccount);
+
+    await program.rpc.collect({
+      accounts: rewardAccounts(nft, stake),
+      signers: [ownerWalletKeypair],
+    });
+
+    const after = await getAccount(provider.connection, rewardTokenAccount);
+    const { lastRewardCollection } = await program.account.stakeAccount.fetch(
+      stake.stakingAccount
+    );
+
+    assert.equal(
+      Number(after.amount - before.amount),
+      150_000 * lastRewardCollection.sub(created).toNumber()
+    );
+
+    await program.rpc.claimAndUnstake({
+      accounts: rewardAccounts(nft, stake),
+      signers: [ownerWalletKeypair],
+    });
+
+    const tokenAccount = await getAccount(provider.connection, nft.tokenAccount);
+    assert.equal(Number(tokenAccount.amount), 1);
+  });
+});
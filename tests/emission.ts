@@ -0,0 +1,306 @@
+import * as anchor from '@project-serum/anchor';
+import { Program } from '@project-serum/anchor';
+import { Staking } from '../target/types/staking';
+import { Keypair, PublicKey } from '@solana/web3.js';
+import { TOKEN_PROGRAM_ID, getAccount, getMint } from '@solana/spl-token';
+import { assert } from 'chai';
+
+import { ownerWalletKeypair, payerKeypair } from './utils/users';
+import { mintNft } from './utils/nft';
+import { getClock, setupPool, stakeToken, waitForClock } from './utils/pool';
+
+// Runs against a local validator with the Token Metadata program loaded from tests/deps (see Anchor.toml)
+describe('shared emission pool', () => {
+  const provider = anchor.Provider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Staking as Program<Staking>;
+
+  const creatorKeypair = Keypair.generate();
+
+  let rewardMint: PublicKey;
+  let rewardTokenAccount: PublicKey;
+  let poolPda: PublicKey;
+  let userStakeSummaryPda: PublicKey;
+  let rewardMintAuthorityPda: PublicKey;
+  let rewardVaultPda: PublicKey;
+  const stakingAccounts: PublicKey[] = [];
+
+  before(async () => {
+    // 100 reward tokens per 10 second day, so 10 per second shared between all staked weight
+    ({
+      pool: poolPda,
+      rewardMint,
+      rewardTokenAccount,
+      rewardMintAuthority: rewardMintAuthorityPda,
+      rewardVault: rewardVaultPda,
+      userStakeSummary: userStakeSummaryPda,
+    } = await setupPool(provider, program, creatorKeypair.publicKey, {
+      baseRewardPerDay: 0,
+      rewardCurve: [{ lockDays: 7, multiplierBps: 10000 }],
+      mode: { shared: {} },
+      emissionPerDay: 100,
+    }));
+  });
+
+  it('Stakes two tokens that share the emissions', async () => {
+    for (let i = 0; i < 2; i++) {
+      const nft = await mintNft(
+        provider.connection,
+        payerKeypair,
+        ownerWalletKeypair.publicKey,
+        creatorKeypair,
+        true
+      );
+
+      const { stakingAccount } = await stakeToken(program, poolPda, nft, 7);
+      stakingAccounts.push(stakingAccount);
+    }
+
+    const pool = await program.account.stakingPool.fetch(poolPda);
+    assert.equal(pool.totalStakedWeight.toNumber(), 20000);
+
+    const stake = await program.account.stakeAccount.fetch(stakingAccounts[0]);
+    assert.equal(stake.weight.toNumber(), 10000);
+  });
+
+  it('Never pays out more than the pool emitted', async () => {
+    // Emissions start when the first token is staked
+    const { created } = await program.account.stakeAccount.fetch(
+      stakingAccounts[0]
+    );
+
+    // Wait for the pool's minimum collection period to pass
+    await waitForClock(provider.connection, created.addn(10));
+
+    const before = await getAccount(provider.connection, rewardTokenAccount);
+
+    await program.rpc.collectMany({
+      accounts: {
+        rewardMintAuthority: rewardMintAuthorityPda,
+        stakingTokenOwner: ownerWalletKeypair.publicKey,
+        userStakeSummary: userStakeSummaryPda,
+        pool: poolPda,
+        rewardMint,
+        rewardVault: rewardVaultPda,
+        ownerRewardTokenAccount: rewardTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      remainingAccounts: stakingAccounts.map((pubkey) => ({
+        pubkey,
+        isWritable: true,
+        isSigner: false,
+      })),
+      signers: [ownerWalletKeypair],
+    });
+
+    const after = await getAccount(provider.connection, rewardTokenAccount);
+    const pool = await program.account.stakingPool.fetch(poolPda);
+
+    const emitted =
+      10 * (pool.lastEmissionUpdate.toNumber() - created.toNumber());
+    const collected = Number(after.amount - before.amount);
+
+    assert.isAbove(collected, 0);
+    assert.isAtMost(collected, emitted);
+  });
+
+  it('Shares the emissions again with a fully collected stake once it is renewed', async () => {
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      creatorKeypair,
+      true
+    );
+
+    // A one day lock, weighted at the curve's first breakpoint
+    const { stakingAccount } = await stakeToken(program, poolPda, nft, 1);
+
+    await program.rpc.setAutoRenew(true, {
+      accounts: {
+        stakingTokenOwner: ownerWalletKeypair.publicKey,
+        stakingAccount,
+      },
+      signers: [ownerWalletKeypair],
+    });
+
+    const collectAccounts = {
+      rewardMintAuthority: rewardMintAuthorityPda,
+      stakingTokenOwner: ownerWalletKeypair.publicKey,
+      ownerStakingTokenAccount: nft.tokenAccount,
+      stakingAccount,
+      userStakeSummary: userStakeSummaryPda,
+      stakingMint: nft.mint,
+      pool: poolPda,
+      rewardMint,
+      rewardVault: rewardVaultPda,
+      ownerRewardTokenAccount: rewardTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+
+    // Wait for the lock to end
+    const { unstakeDate } = await program.account.stakeAccount.fetch(
+      stakingAccount
+    );
+    await waitForClock(provider.connection, unstakeDate);
+
+    await program.rpc.collectFull({
+      accounts: collectAccounts,
+      signers: [ownerWalletKeypair],
+    });
+
+    let stake = await program.account.stakeAccount.fetch(stakingAccount);
+    assert.equal(stake.weight.toNumber(), 0);
+
+    await program.rpc.renew({
+      accounts: {
+        rewardMintAuthority: rewardMintAuthorityPda,
+        cranker: payerKeypair.publicKey,
+        userStakeSummary: userStakeSummaryPda,
+        stakingAccount,
+        pool: poolPda,
+        rewardMint,
+        rewardVault: rewardVaultPda,
+        ownerRewardTokenAccount: rewardTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      signers: [payerKeypair],
+    });
+
+    stake = await program.account.stakeAccount.fetch(stakingAccount);
+    assert.equal(stake.weight.toNumber(), 10000);
+
+    const pool = await program.account.stakingPool.fetch(poolPda);
+    assert.equal(pool.totalStakedWeight.toNumber(), 30000);
+
+    // Wait for the pool's minimum collection period to pass
+    await waitForClock(provider.connection, stake.lastRewardCollection.addn(10));
+
+    const before = await getAccount(provider.connection, rewardTokenAccount);
+
+    await program.rpc.collect({
+      accounts: collectAccounts,
+      signers: [ownerWalletKeypair],
+    });
+
+    const after = await getAccount(provider.connection, rewardTokenAccount);
+    assert.isAbove(Number(after.amount - before.amount), 0);
+  });
+
+  it('Keeps emitting over a long gap between updates', async () => {
+    // 10,000 reward tokens of 9 decimals per 10 second day. Scaled by REWARD_PRECISION and the emission schedule's basis points,
+    // what an 11 second gap emits is more than fits in a u128, so the pool has to divide it between the staked weight first.
+    const emissionPerDay = 10_000_000_000_000;
+    const {
+      pool,
+      rewardMint: mint,
+      rewardTokenAccount: tokenAccount,
+      rewardMintAuthority,
+      rewardVault,
+      userStakeSummary,
+    } = await setupPool(
+      provider,
+      program,
+      creatorKeypair.publicKey,
+      {
+        baseRewardPerDay: 0,
+        rewardCurve: [{ lockDays: 7, multiplierBps: 10000 }],
+        mode: { shared: {} },
+        emissionPerDay,
+      },
+      true,
+      9
+    );
+
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      creatorKeypair,
+      true
+    );
+
+    const { stakingAccount } = await stakeToken(program, pool, nft, 7);
+
+    const { created } = await program.account.stakeAccount.fetch(stakingAccount);
+
+    await waitForClock(provider.connection, created.addn(11));
+
+    await program.rpc.collect({
+      accounts: {
+        rewardMintAuthority,
+        stakingTokenOwner: ownerWalletKeypair.publicKey,
+        ownerStakingTokenAccount: nft.tokenAccount,
+        stakingAccount,
+        userStakeSummary,
+        stakingMint: nft.mint,
+        pool,
+        rewardMint: mint,
+        rewardVault,
+        ownerRewardTokenAccount: tokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      signers: [ownerWalletKeypair],
+    });
+
+    // The only stake is paid everything emitted since it was staked, less rounding
+    const rewards = await getAccount(provider.connection, tokenAccount);
+    const { lastEmissionUpdate } = await program.account.stakingPool.fetch(pool);
+    const emitted =
+      (emissionPerDay / 10) * (lastEmissionUpdate.toNumber() - created.toNumber());
+
+    assert.isAbove(Number(rewards.amount), 0);
+    assert.isAtMost(Number(rewards.amount), emitted);
+  });
+
+  it('Time-locks the release of the reward mint authority for two days', async () => {
+    const timestamp = await getClock(provider.connection);
+
+    await program.rpc.requestMintAuthorityRelease(
+      ownerWalletKeypair.publicKey,
+      {
+        accounts: {
+          pool: poolPda,
+          admin: ownerWalletKeypair.publicKey,
+        },
+        signers: [ownerWalletKeypair],
+      }
+    );
+
+    const pool = await program.account.stakingPool.fetch(poolPda);
+    assert.ok(pool.pendingMintAuthority.equals(ownerWalletKeypair.publicKey));
+    // The timelock does not follow the pool's 10 second days
+    assert.isAtLeast(
+      pool.mintAuthorityReleaseTime.toNumber(),
+      timestamp.toNumber() + 2 * 86400
+    );
+
+    try {
+      await program.rpc.releaseMintAuthority({
+        accounts: {
+          rewardMintAuthority: rewardMintAuthorityPda,
+          pool: poolPda,
+          rewardMint,
+          admin: ownerWalletKeypair.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        },
+        signers: [ownerWalletKeypair],
+      });
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 6017);
+    }
+
+    await program.rpc.cancelMintAuthorityRelease({
+      accounts: {
+        pool: poolPda,
+        admin: ownerWalletKeypair.publicKey,
+      },
+      signers: [ownerWalletKeypair],
+    });
+
+    const mint = await getMint(provider.connection, rewardMint);
+    assert.ok(mint.mintAuthority.equals(rewardMintAuthorityPda));
+  });
+});
@@ -0,0 +1,291 @@
+import * as anchor from '@project-serum/anchor';
+import { Program } from '@project-serum/anchor';
+import { Staking } from '../target/types/staking';
+import { Keypair, PublicKey } from '@solana/web3.js';
+import {
+  TOKEN_PROGRAM_ID,
+  createMint,
+  getAccount,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from '@solana/spl-token';
+import { assert } from 'chai';
+
+import { ownerWalletKeypair, payerKeypair } from './utils/users';
+import { mintNft } from './utils/nft';
+import {
+  findPoolPda,
+  setupPool,
+  stakeToken,
+  waitForClock,
+} from './utils/pool';
+
+// Mint standing for SOL revenue
+const SOL_REVENUE_MINT = anchor.web3.SystemProgram.programId;
+
+// Runs against a local validator with the Token Metadata program loaded from tests/deps (see Anchor.toml)
+describe('revenue sharing', () => {
+  const provider = anchor.Provider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Staking as Program<Staking>;
+
+  const creatorKeypair = Keypair.generate();
+
+  let rewardTokenAccount: PublicKey;
+  let usdcMint: PublicKey;
+  let depositorUsdcAccount: PublicKey;
+  let ownerUsdcAccount: PublicKey;
+  let poolPda: PublicKey;
+  let userStakeSummaryPda: PublicKey;
+  let stakingAccount: PublicKey;
+
+  const findPda = (seed: string, mint: PublicKey) =>
+    findPoolPda(program, seed, poolPda, mint);
+
+  const addRevenueToken = async (revenueMint: PublicKey) => {
+    await program.rpc.addRevenueToken({
+      accounts: {
+        pool: poolPda,
+        revenueMint,
+        revenueVault: await findPda('revenue_vault', revenueMint),
+        revenueAuthority: await findPda('authority', revenueMint),
+        admin: ownerWalletKeypair.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      signers: [ownerWalletKeypair],
+    });
+  };
+
+  const depositRevenue = async (
+    revenueMint: PublicKey,
+    depositorTokenAccount: PublicKey,
+    amount: number
+  ) => {
+    await program.rpc.depositRevenue(new anchor.BN(amount), {
+      accounts: {
+        depositor: payerKeypair.publicKey,
+        depositorTokenAccount,
+        pool: poolPda,
+        revenueMint,
+        revenueVault: await findPda('revenue_vault', revenueMint),
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      signers: [payerKeypair],
+    });
+  };
+
+  const claimRevenueAccounts = async (
+    revenueMint: PublicKey,
+    ownerRevenueTokenAccount: PublicKey,
+    stake: PublicKey
+  ) => ({
+    stakingTokenOwner: ownerWalletKeypair.publicKey,
+    stakingAccount: stake,
+    pool: poolPda,
+    revenueMint,
+    revenueVault: await findPda('revenue_vault', revenueMint),
+    revenueAuthority: await findPda('authority', revenueMint),
+    ownerRevenueTokenAccount,
+    systemProgram: anchor.web3.SystemProgram.programId,
+    tokenProgram: TOKEN_PROGRAM_ID,
+  });
+
+  const claimRevenue = async (
+    revenueMint: PublicKey,
+    ownerRevenueTokenAccount: PublicKey
+  ) => {
+    await program.rpc.claimRevenue({
+      accounts: await claimRevenueAccounts(
+        revenueMint,
+        ownerRevenueTokenAccount,
+        stakingAccount
+      ),
+      signers: [ownerWalletKeypair],
+    });
+  };
+
+  before(async () => {
+    // 5 reward tokens per 10 second day
+    ({
+      pool: poolPda,
+      rewardTokenAccount,
+      userStakeSummary: userStakeSummaryPda,
+    } = await setupPool(provider, program, creatorKeypair.publicKey, {}, false));
+
+    usdcMint = await createMint(
+      provider.connection,
+      payerKeypair,
+      payerKeypair.publicKey,
+      null,
+      6
+    );
+
+    ownerUsdcAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        payerKeypair,
+        usdcMint,
+        ownerWalletKeypair.publicKey
+      )
+    ).address;
+    depositorUsdcAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        payerKeypair,
+        usdcMint,
+        payerKeypair.publicKey
+      )
+    ).address;
+
+    await mintTo(
+      provider.connection,
+      payerKeypair,
+      usdcMint,
+      depositorUsdcAccount,
+      payerKeypair,
+      700_000_000
+    );
+  });
+
+  it('Registers SOL and a token as revenue of a pool', async () => {
+    // Lamports sent to the token's vault address ahead of time do not stop it being created
+    await provider.send(
+      new anchor.web3.Transaction().add(
+        anchor.web3.SystemProgram.transfer({
+          fromPubkey: payerKeypair.publicKey,
+          toPubkey: await findPda('revenue_vault', usdcMint),
+          lamports: 1000,
+        })
+      ),
+      [payerKeypair]
+    );
+
+    await addRevenueToken(SOL_REVENUE_MINT);
+    await addRevenueToken(usdcMint);
+
+    const pool = await program.account.stakingPool.fetch(poolPda);
+    assert.equal(pool.revenueTokens.length, 2);
+  });
+
+  it('Shares deposited revenue with a staked token', async () => {
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      creatorKeypair,
+      true
+    );
+
+    ({ stakingAccount } = await stakeToken(program, poolPda, nft, 1));
+
+    const pool = await program.account.stakingPool.fetch(poolPda);
+    assert.equal(pool.totalStaked.toNumber(), 1);
+
+    // Both deposits are shared over seven 10 second days
+    await depositRevenue(SOL_REVENUE_MINT, payerKeypair.publicKey, 70_000_000);
+    await depositRevenue(usdcMint, depositorUsdcAccount, 700_000_000);
+
+    const { created } = await program.account.stakeAccount.fetch(stakingAccount);
+    await waitForClock(provider.connection, created.addn(11));
+
+    const lamportsBefore = await provider.connection.getBalance(
+      ownerWalletKeypair.publicKey
+    );
+    await claimRevenue(SOL_REVENUE_MINT, ownerWalletKeypair.publicKey);
+    const lamportsAfter = await provider.connection.getBalance(
+      ownerWalletKeypair.publicKey
+    );
+
+    assert.isAbove(lamportsAfter, lamportsBefore);
+    assert.isBelow(lamportsAfter - lamportsBefore, 70_000_000);
+
+    await claimRevenue(usdcMint, ownerUsdcAccount);
+
+    const usdc = await getAccount(provider.connection, ownerUsdcAccount);
+    assert.isAbove(Number(usdc.amount), 0);
+    assert.isBelow(Number(usdc.amount), 700_000_000);
+
+    const stake = await program.account.stakeAccount.fetch(stakingAccount);
+    assert.equal(stake.revenueShares[1].claimed.toNumber(), Number(usdc.amount));
+  });
+
+  it('Fails to unstake a token until its revenue is claimed', async () => {
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      creatorKeypair,
+      true
+    );
+
+    // A five day lock, so that it can be left early
+    const {
+      stakingAccount: stake,
+      vaultAccount,
+      vaultAuthority,
+    } = await stakeToken(program, poolPda, nft, 5);
+
+    // Let the token earn some of both deposits
+    const { created } = await program.account.stakeAccount.fetch(stake);
+    await waitForClock(provider.connection, created.addn(3));
+
+    const unstakeAccounts = {
+      stakingTokenOwner: ownerWalletKeypair.publicKey,
+      stakingMint: nft.mint,
+      vaultAccount,
+      vaultAuthority,
+      ownerStakingTokenAccount: nft.tokenAccount,
+      stakingAccount: stake,
+      userStakeSummary: userStakeSummaryPda,
+      pool: poolPda,
+      ownerRewardTokenAccount: rewardTokenAccount,
+      treasury: ownerWalletKeypair.publicKey,
+      systemProgram: anchor.web3.SystemProgram.programId,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+
+    try {
+      await program.rpc.earlyUnstake({
+        accounts: unstakeAccounts,
+        signers: [ownerWalletKeypair],
+      });
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 6021);
+    }
+
+    const usdcBefore = await getAccount(provider.connection, ownerUsdcAccount);
+
+    // Revenue accrues every second, so it is claimed in the same transaction as the unstake
+    const tx = new anchor.web3.Transaction();
+    tx.add(
+      program.instruction.claimRevenue({
+        accounts: await claimRevenueAccounts(
+          SOL_REVENUE_MINT,
+          ownerWalletKeypair.publicKey,
+          stake
+        ),
+      })
+    );
+    // Claiming again finds less than a whole unit pending, which does not fail the transaction
+    for (let i = 0; i < 2; i++) {
+      tx.add(
+        program.instruction.claimRevenue({
+          accounts: await claimRevenueAccounts(usdcMint, ownerUsdcAccount, stake),
+        })
+      );
+    }
+    tx.add(program.instruction.earlyUnstake({ accounts: unstakeAccounts }));
+    await provider.send(tx, [ownerWalletKeypair]);
+
+    const usdcAfter = await getAccount(provider.connection, ownerUsdcAccount);
+    assert.isAbove(Number(usdcAfter.amount - usdcBefore.amount), 0);
+
+    const pool = await program.account.stakingPool.fetch(poolPda);
+    assert.equal(pool.totalStaked.toNumber(), 1);
+  });
+});
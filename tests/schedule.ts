@@ -0,0 +1,120 @@
+import * as anchor from '@project-serum/anchor';
+import { Program } from '@project-serum/anchor';
+import { Staking } from '../target/types/staking';
+import { Keypair, PublicKey } from '@solana/web3.js';
+import { TOKEN_PROGRAM_ID, getAccount } from '@solana/spl-token';
+import { assert } from 'chai';
+
+import { ownerWalletKeypair, payerKeypair } from './utils/users';
+import { mintNft } from './utils/nft';
+import { setupPool, stakeToken, waitForClock } from './utils/pool';
+
+// Runs against a local validator with the Token Metadata program loaded from tests/deps (see Anchor.toml)
+describe('emission schedule', () => {
+  const provider = anchor.Provider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Staking as Program<Staking>;
+
+  const creatorKeypair = Keypair.generate();
+
+  let rewardMint: PublicKey;
+  let rewardTokenAccount: PublicKey;
+  let poolPda: PublicKey;
+  let userStakeSummaryPda: PublicKey;
+  let rewardMintAuthorityPda: PublicKey;
+  let rewardVaultPda: PublicKey;
+
+  const setEmissionSchedule = async (
+    steps: { startTime: anchor.BN; multiplierBps: number }[]
+  ) => {
+    await program.rpc.setEmissionSchedule(steps, {
+      accounts: {
+        pool: poolPda,
+        admin: ownerWalletKeypair.publicKey,
+      },
+      signers: [ownerWalletKeypair],
+    });
+  };
+
+  before(async () => {
+    // 5 reward tokens per 10 second day
+    ({
+      pool: poolPda,
+      rewardMint,
+      rewardTokenAccount,
+      rewardMintAuthority: rewardMintAuthorityPda,
+      rewardVault: rewardVaultPda,
+      userStakeSummary: userStakeSummaryPda,
+    } = await setupPool(provider, program, creatorKeypair.publicKey));
+  });
+
+  it('Halves the reward of a lock from the middle of it', async () => {
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      creatorKeypair,
+      true
+    );
+
+    const { stakingAccount, vaultAccount, vaultAuthority } = await stakeToken(
+      program,
+      poolPda,
+      nft
+    );
+
+    // Halve the rate for the second half of the 10 second lock
+    const { created, unstakeDate } = await program.account.stakeAccount.fetch(
+      stakingAccount
+    );
+    const halving = { startTime: created.addn(5), multiplierBps: 5000 };
+
+    await setEmissionSchedule([halving]);
+
+    // Steps must be in increasing order of start time
+    try {
+      await setEmissionSchedule([
+        halving,
+        { startTime: created.addn(4), multiplierBps: 2500 },
+      ]);
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 6005);
+    }
+
+    // Wait for the one day lock to end
+    await waitForClock(provider.connection, unstakeDate);
+
+    // A step that has already started cannot be removed
+    try {
+      await setEmissionSchedule([]);
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 6005);
+    }
+
+    await program.rpc.claimAndUnstake({
+      accounts: {
+        rewardMintAuthority: rewardMintAuthorityPda,
+        stakingTokenOwner: ownerWalletKeypair.publicKey,
+        stakingMint: nft.mint,
+        vaultAccount,
+        vaultAuthority,
+        ownerStakingTokenAccount: nft.tokenAccount,
+        stakingAccount,
+        userStakeSummary: userStakeSummaryPda,
+        pool: poolPda,
+        rewardMint,
+        rewardVault: rewardVaultPda,
+        ownerRewardTokenAccount: rewardTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      signers: [ownerWalletKeypair],
+    });
+
+    // 5 tokens over the first half at the full rate and the second half at half rate, rounded down
+    const rewards = await getAccount(provider.connection, rewardTokenAccount);
+    assert.equal(Number(rewards.amount), 3);
+  });
+});
@@ -424,6 +424,26 @@ describe('staking', () => {
       )
     ).value;
 
+    const [rewardVaultPda, _reward_vault_bump] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode('reward_vault')),
+          selectedToken[0].account.pool.toBuffer(),
+          rewardMintPk.toBuffer(),
+        ],
+        program.programId
+      );
+
+    const [userStakeSummaryPda, _user_stake_summary_bump] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode('user')),
+          selectedToken[0].account.pool.toBuffer(),
+          initializerMainAccount.publicKey.toBuffer(),
+        ],
+        program.programId
+      );
+
     await program.rpc.collectFull({
       accounts: {
         rewardMintAuthority: rewardMintAuthorityKeypair.publicKey,
@@ -431,8 +451,11 @@ describe('staking', () => {
         ownerStakingTokenAccount:
           selectedToken[0].account.ownerStakingTokenAccount,
         stakingAccount: selectedToken[0].publicKey,
+        userStakeSummary: userStakeSummaryPda,
         stakingMint: selectedToken[0].account.stakingMint,
+        pool: selectedToken[0].account.pool,
         rewardMint: rewardMintPk,
+        rewardVault: rewardVaultPda,
         ownerRewardTokenAccount: retrievedRewardAta[0].pubkey,
         tokenProgram: TOKEN_PROGRAM_ID,
       },
@@ -482,6 +505,16 @@ describe('staking', () => {
 
     const vault_authority_pda = _vault_authority_pda;
 
+    const [userStakeSummaryPda, _user_stake_summary_bump] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode('user')),
+          stakedToken[0].account.pool.toBuffer(),
+          initializerMainAccount.publicKey.toBuffer(),
+        ],
+        program.programId
+      );
+
     await program.rpc.unstake({
       accounts: {
         stakingTokenOwner: initializerMainAccount.publicKey,
@@ -492,6 +525,8 @@ describe('staking', () => {
         vaultAccount: vault_account_pda,
         vaultAuthority: vault_authority_pda,
         stakingAccount: stakedToken[0].publicKey,
+        userStakeSummary: userStakeSummaryPda,
+        pool: stakedToken[0].account.pool,
         tokenProgram: TOKEN_PROGRAM_ID,
       },
       signers: [initializerMainAccount],
@@ -0,0 +1,264 @@
+import * as anchor from '@project-serum/anchor';
+import { Program } from '@project-serum/anchor';
+import { Staking } from '../target/types/staking';
+import { Keypair, PublicKey } from '@solana/web3.js';
+import {
+  TOKEN_PROGRAM_ID,
+  createMint,
+  getAccount,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from '@solana/spl-token';
+import { assert } from 'chai';
+
+import { ownerWalletKeypair, payerKeypair } from './utils/users';
+import { mintNft } from './utils/nft';
+import {
+  findPoolPda,
+  setupPool,
+  stakeToken,
+  waitForClock,
+} from './utils/pool';
+
+// Runs against a local validator with the Token Metadata program loaded from tests/deps (see Anchor.toml)
+describe('reward streams', () => {
+  const provider = anchor.Provider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Staking as Program<Staking>;
+
+  const creatorKeypair = Keypair.generate();
+
+  let rewardMint: PublicKey;
+  let rewardTokenAccount: PublicKey;
+  let partnerMint: PublicKey;
+  let partnerTokenAccount: PublicKey;
+  let poolPda: PublicKey;
+  let userStakeSummaryPda: PublicKey;
+  let rewardMintAuthorityPda: PublicKey;
+  let rewardVaultPda: PublicKey;
+  let partnerMintAuthorityPda: PublicKey;
+  let partnerVaultPda: PublicKey;
+
+  // The accounts paying the pool's only stream
+  const partnerStreamAccounts = () => [
+    { pubkey: partnerMint, isWritable: true, isSigner: false },
+    { pubkey: partnerMintAuthorityPda, isWritable: false, isSigner: false },
+    { pubkey: partnerVaultPda, isWritable: true, isSigner: false },
+    { pubkey: partnerTokenAccount, isWritable: true, isSigner: false },
+  ];
+
+  before(async () => {
+    // 5 reward tokens per 10 second day
+    ({
+      pool: poolPda,
+      rewardMint,
+      rewardTokenAccount,
+      rewardMintAuthority: rewardMintAuthorityPda,
+      rewardVault: rewardVaultPda,
+      userStakeSummary: userStakeSummaryPda,
+    } = await setupPool(provider, program, creatorKeypair.publicKey));
+  });
+
+  it('Adds a partner token stream to a pool', async () => {
+    partnerMint = await createMint(
+      provider.connection,
+      payerKeypair,
+      payerKeypair.publicKey,
+      null,
+      0
+    );
+
+    partnerTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        payerKeypair,
+        partnerMint,
+        ownerWalletKeypair.publicKey
+      )
+    ).address;
+
+    partnerMintAuthorityPda = await findPoolPda(program, 'authority', poolPda, partnerMint);
+    partnerVaultPda = await findPoolPda(program, 'reward_vault', poolPda, partnerMint);
+
+    // 10 partner tokens per day, paid from the stream's vault
+    await program.rpc.addRewardStream(new anchor.BN(10), { vault: {} }, {
+      accounts: {
+        streamVault: partnerVaultPda,
+        streamMintAuthority: partnerMintAuthorityPda,
+        pool: poolPda,
+        streamMint: partnerMint,
+        admin: ownerWalletKeypair.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      signers: [ownerWalletKeypair],
+    });
+
+    const funderTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        payerKeypair,
+        partnerMint,
+        payerKeypair.publicKey
+      )
+    ).address;
+
+    await mintTo(
+      provider.connection,
+      payerKeypair,
+      partnerMint,
+      funderTokenAccount,
+      payerKeypair,
+      100
+    );
+
+    await program.rpc.fundPool(new anchor.BN(100), {
+      accounts: {
+        funder: payerKeypair.publicKey,
+        funderTokenAccount,
+        pool: poolPda,
+        rewardVault: partnerVaultPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      signers: [payerKeypair],
+    });
+
+    const pool = await program.account.stakingPool.fetch(poolPda);
+    assert.equal(pool.rewardStreams.length, 1);
+    assert.ok(pool.rewardStreams[0].mint.equals(partnerMint));
+  });
+
+  it('Pays the reward mint and the partner stream when unstaking', async () => {
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      creatorKeypair,
+      true
+    );
+
+    const { stakingAccount, vaultAccount, vaultAuthority } = await stakeToken(
+      program,
+      poolPda,
+      nft
+    );
+
+    // Wait for the one day lock to end
+    const { unstakeDate } = await program.account.stakeAccount.fetch(
+      stakingAccount
+    );
+    await waitForClock(provider.connection, unstakeDate);
+
+    const claimAccounts = {
+      rewardMintAuthority: rewardMintAuthorityPda,
+      stakingTokenOwner: ownerWalletKeypair.publicKey,
+      stakingMint: nft.mint,
+      vaultAccount,
+      vaultAuthority,
+      ownerStakingTokenAccount: nft.tokenAccount,
+      stakingAccount,
+      userStakeSummary: userStakeSummaryPda,
+      pool: poolPda,
+      rewardMint,
+      rewardVault: rewardVaultPda,
+      ownerRewardTokenAccount: rewardTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+
+    // Every stream of the pool has to be paid
+    try {
+      await program.rpc.claimAndUnstake({
+        accounts: claimAccounts,
+        signers: [ownerWalletKeypair],
+      });
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 6018);
+    }
+
+    await program.rpc.claimAndUnstake({
+      accounts: claimAccounts,
+      remainingAccounts: partnerStreamAccounts(),
+      signers: [ownerWalletKeypair],
+    });
+
+    const rewards = await getAccount(provider.connection, rewardTokenAccount);
+    assert.equal(Number(rewards.amount), 5);
+
+    const partnerRewards = await getAccount(
+      provider.connection,
+      partnerTokenAccount
+    );
+    assert.equal(Number(partnerRewards.amount), 10);
+  });
+
+  it('Pays the partner stream when unstaking after collect_many', async () => {
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      creatorKeypair,
+      true
+    );
+
+    const { stakingAccount, vaultAccount, vaultAuthority } = await stakeToken(
+      program,
+      poolPda,
+      nft
+    );
+
+    const { unstakeDate } = await program.account.stakeAccount.fetch(
+      stakingAccount
+    );
+    await waitForClock(provider.connection, unstakeDate);
+
+    // collect_many pays the full reward, but only of the reward mint
+    await program.rpc.collectMany({
+      accounts: {
+        rewardMintAuthority: rewardMintAuthorityPda,
+        stakingTokenOwner: ownerWalletKeypair.publicKey,
+        userStakeSummary: userStakeSummaryPda,
+        pool: poolPda,
+        rewardMint,
+        rewardVault: rewardVaultPda,
+        ownerRewardTokenAccount: rewardTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      remainingAccounts: [
+        { pubkey: stakingAccount, isWritable: true, isSigner: false },
+      ],
+      signers: [ownerWalletKeypair],
+    });
+
+    const stake = await program.account.stakeAccount.fetch(stakingAccount);
+    assert.isTrue(stake.fullRewardCollected);
+
+    await program.rpc.unstake({
+      accounts: {
+        stakingTokenOwner: ownerWalletKeypair.publicKey,
+        stakingMint: nft.mint,
+        vaultAccount,
+        vaultAuthority,
+        ownerStakingTokenAccount: nft.tokenAccount,
+        stakingAccount,
+        userStakeSummary: userStakeSummaryPda,
+        pool: poolPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      remainingAccounts: partnerStreamAccounts(),
+      signers: [ownerWalletKeypair],
+    });
+
+    const rewards = await getAccount(provider.connection, rewardTokenAccount);
+    assert.equal(Number(rewards.amount), 10);
+
+    // 10 from the previous test, and 10 for this lock
+    const partnerRewards = await getAccount(
+      provider.connection,
+      partnerTokenAccount
+    );
+    assert.equal(Number(partnerRewards.amount), 20);
+  });
+});
@@ -0,0 +1,81 @@
+import {
+  Connection,
+  Keypair,
+  PublicKey,
+  sendAndConfirmTransaction,
+} from '@solana/web3.js';
+import {
+  createMint,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from '@solana/spl-token';
+import {
+  Creator,
+  CreateMetadataV2,
+  DataV2,
+  Metadata,
+  SignMetadata,
+} from '@metaplex-foundation/mpl-token-metadata';
+
+// Mint a single token with Token Metadata to the owner. If verifyCreator is set, the creator signs the metadata to verify itself.
+export const mintNft = async (
+  connection: Connection,
+  payer: Keypair,
+  owner: PublicKey,
+  creator: Keypair,
+  verifyCreator: boolean
+) => {
+  const mint = await createMint(connection, payer, payer.publicKey, null, 0);
+
+  const tokenAccount = await getOrCreateAssociatedTokenAccount(
+    connection,
+    payer,
+    mint,
+    owner
+  );
+
+  await mintTo(connection, payer, mint, tokenAccount.address, payer, 1);
+
+  const metadata = await Metadata.getPDA(mint);
+
+  const createMetadataTx = new CreateMetadataV2(
+    { feePayer: payer.publicKey },
+    {
+      metadata,
+      metadataData: new DataV2({
+        name: 'Staking Test',
+        symbol: 'STK',
+        uri: '',
+        sellerFeeBasisPoints: 0,
+        creators: [
+          new Creator({
+            address: creator.publicKey.toBase58(),
+            verified: false,
+            share: 100,
+          }),
+        ],
+        collection: null,
+        uses: null,
+      }),
+      updateAuthority: payer.publicKey,
+      mint,
+      mintAuthority: payer.publicKey,
+    }
+  );
+
+  await sendAndConfirmTransaction(connection, createMetadataTx, [payer]);
+
+  if (verifyCreator) {
+    const signMetadataTx = new SignMetadata(
+      { feePayer: payer.publicKey },
+      { metadata, creator: creator.publicKey }
+    );
+
+    await sendAndConfirmTransaction(connection, signMetadataTx, [
+      payer,
+      creator,
+    ]);
+  }
+
+  return { mint, tokenAccount: tokenAccount.address, metadata };
+};
@@ -0,0 +1,277 @@
+import * as anchor from '@project-serum/anchor';
+import { Program } from '@project-serum/anchor';
+import { Staking } from '../../target/types/staking';
+import {
+  Connection,
+  Keypair,
+  LAMPORTS_PER_SOL,
+  PublicKey,
+  SYSVAR_CLOCK_PUBKEY,
+} from '@solana/web3.js';
+import {
+  TOKEN_PROGRAM_ID,
+  createMint,
+  getAssociatedTokenAddress,
+  getOrCreateAssociatedTokenAccount,
+  setAuthority,
+  AuthorityType,
+} from '@solana/spl-token';
+
+import { ownerWalletKeypair, payerKeypair } from './users';
+
+// Arguments of initialize_pool, other than the collection and its creators
+export type PoolConfig = {
+  minLockDays: number;
+  maxLockDays: number;
+  baseRewardPerDay: number;
+  rewardCurve: { lockDays: number; multiplierBps: number }[];
+  rarityTiers: { multiplierBps: number; isOneOfOne: boolean }[];
+  minimumCollectionPeriod: number;
+  dayLength: number;
+  mode: { fixed: {} } | { shared: {} };
+  emissionPerDay: number;
+};
+
+// 5 reward tokens per 10 second day, for a one day lock
+export const DEFAULT_POOL_CONFIG: PoolConfig = {
+  minLockDays: 1,
+  maxLockDays: 50,
+  baseRewardPerDay: 5,
+  rewardCurve: [{ lockDays: 1, multiplierBps: 10000 }],
+  rarityTiers: [{ multiplierBps: 10000, isOneOfOne: false }],
+  minimumCollectionPeriod: 10,
+  dayLength: 10,
+  mode: { fixed: {} },
+  emissionPerDay: 0,
+};
+
+// Address of a PDA seeded by the pool and another key, e.g. ('stake', mint) or ('user', owner)
+export const findPoolPda = async (
+  program: Program<Staking>,
+  seed: string,
+  pool: PublicKey,
+  key: PublicKey
+) => {
+  const [pda] = await PublicKey.findProgramAddress(
+    [
+      Buffer.from(anchor.utils.bytes.utf8.encode(seed)),
+      pool.toBuffer(),
+      key.toBuffer(),
+    ],
+    program.programId
+  );
+  return pda;
+};
+
+// Addresses of the accounts holding a token staked in a pool
+export const findStakeAccounts = async (
+  program: Program<Staking>,
+  pool: PublicKey,
+  mint: PublicKey
+) => {
+  const stakingAccount = await findPoolPda(program, 'stake', pool, mint);
+
+  const [vaultAccount] = await PublicKey.findProgramAddress(
+    [
+      Buffer.from(anchor.utils.bytes.utf8.encode('receipt')),
+      stakingAccount.toBuffer(),
+      mint.toBuffer(),
+    ],
+    program.programId
+  );
+
+  const [vaultAuthority] = await PublicKey.findProgramAddress(
+    [
+      Buffer.from(anchor.utils.bytes.utf8.encode('vault')),
+      stakingAccount.toBuffer(),
+      mint.toBuffer(),
+    ],
+    program.programId
+  );
+
+  return {
+    stakingAccount,
+    vaultAccount,
+    vaultAuthority,
+    rarityRecord: await findPoolPda(program, 'rarity', pool, mint),
+  };
+};
+
+// Fund the owner and payer wallets, create a reward mint with rewardDecimals decimals (none by default) and the owner's
+// token account for it, and initialize a pool for a new collection administered by the owner. If mintRewards is set,
+// the reward mint authority is handed to the pool's mint authority PDA.
+export const setupPool = async (
+  provider: anchor.Provider,
+  program: Program<Staking>,
+  creator: PublicKey,
+  config: Partial<PoolConfig> = {},
+  mintRewards = true,
+  rewardDecimals = 0
+) => {
+  const {
+    minLockDays,
+    maxLockDays,
+    baseRewardPerDay,
+    rewardCurve,
+    rarityTiers,
+    minimumCollectionPeriod,
+    dayLength,
+    mode,
+    emissionPerDay,
+  } = { ...DEFAULT_POOL_CONFIG, ...config };
+
+  for (const keypair of [ownerWalletKeypair, payerKeypair]) {
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(
+        keypair.publicKey,
+        10 * LAMPORTS_PER_SOL
+      )
+    );
+  }
+
+  const rewardMint = await createMint(
+    provider.connection,
+    payerKeypair,
+    payerKeypair.publicKey,
+    null,
+    rewardDecimals
+  );
+
+  const rewardTokenAccount = (
+    await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payerKeypair,
+      rewardMint,
+      ownerWalletKeypair.publicKey
+    )
+  ).address;
+
+  const collection = Keypair.generate().publicKey;
+  const [pool] = await PublicKey.findProgramAddress(
+    [Buffer.from(anchor.utils.bytes.utf8.encode('pool')), collection.toBuffer()],
+    program.programId
+  );
+
+  await program.rpc.initializePool(
+    collection,
+    [creator],
+    minLockDays,
+    maxLockDays,
+    new anchor.BN(baseRewardPerDay),
+    rewardCurve,
+    rarityTiers,
+    new anchor.BN(minimumCollectionPeriod),
+    new anchor.BN(dayLength),
+    mode,
+    new anchor.BN(emissionPerDay),
+    {
+      accounts: {
+        pool,
+        rewardMint,
+        admin: ownerWalletKeypair.publicKey,
+        authority: ownerWalletKeypair.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [ownerWalletKeypair],
+    }
+  );
+
+  const rewardMintAuthority = await findPoolPda(program, 'authority', pool, rewardMint);
+
+  if (mintRewards) {
+    await program.rpc.initMintAuthority({
+      accounts: {
+        mintAuthority: rewardMintAuthority,
+        pool,
+        rewardMint,
+        admin: ownerWalletKeypair.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [ownerWalletKeypair],
+    });
+
+    await setAuthority(
+      provider.connection,
+      payerKeypair,
+      rewardMint,
+      payerKeypair,
+      AuthorityType.MintTokens,
+      rewardMintAuthority
+    );
+  }
+
+  return {
+    collection,
+    pool,
+    rewardMint,
+    rewardTokenAccount,
+    rewardMintAuthority,
+    rewardVault: await findPoolPda(program, 'reward_vault', pool, rewardMint),
+    userStakeSummary: await findPoolPda(
+      program,
+      'user',
+      pool,
+      ownerWalletKeypair.publicKey
+    ),
+  };
+};
+
+// Stake a token minted to the owner by mintNft in a pool set up by setupPool, locked for lockDays days.
+// Returns the addresses of the accounts holding the staked token.
+export const stakeToken = async (
+  program: Program<Staking>,
+  pool: PublicKey,
+  nft: { mint: PublicKey; metadata: PublicKey; tokenAccount: PublicKey },
+  lockDays = 1
+) => {
+  const { rewardMint } = await program.account.stakingPool.fetch(pool);
+  const stakeAccounts = await findStakeAccounts(program, pool, nft.mint);
+
+  await program.rpc.stake(lockDays, {
+    accounts: {
+      stakingTokenOwner: ownerWalletKeypair.publicKey,
+      stakingMint: nft.mint,
+      stakingMintMetadata: nft.metadata,
+      vaultAccount: stakeAccounts.vaultAccount,
+      ownerStakingTokenAccount: nft.tokenAccount,
+      ownerRewardTokenAccount: await getAssociatedTokenAddress(
+        rewardMint,
+        ownerWalletKeypair.publicKey
+      ),
+      stakingAccount: stakeAccounts.stakingAccount,
+      userStakeSummary: await findPoolPda(
+        program,
+        'user',
+        pool,
+        ownerWalletKeypair.publicKey
+      ),
+      pool,
+      rarityRecord: stakeAccounts.rarityRecord,
+      systemProgram: anchor.web3.SystemProgram.programId,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    },
+    signers: [ownerWalletKeypair],
+  });
+
+  return stakeAccounts;
+};
+
+// Read the cluster's clock, the time seen by the program
+export const getClock = async (connection: Connection) => {
+  const clock = await connection.getAccountInfo(SYSVAR_CLOCK_PUBKEY);
+  // unix_timestamp follows slot, epoch_start_timestamp, epoch and leader_schedule_epoch
+  return new anchor.BN(clock.data.slice(32, 40), 'le');
+};
+
+// Wait until the cluster's clock has reached timestamp
+export const waitForClock = async (
+  connection: Connection,
+  timestamp: number | anchor.BN
+) => {
+  while ((await getClock(connection)).lt(new anchor.BN(timestamp))) {
+    await new Promise((resolve) => setTimeout(resolve, 500));
+  }
+};
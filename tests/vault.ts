@@ -0,0 +1,174 @@
+import * as anchor from '@project-serum/anchor';
+import { Program } from '@project-serum/anchor';
+import { Staking } from '../target/types/staking';
+import { Keypair, PublicKey } from '@solana/web3.js';
+import {
+  TOKEN_PROGRAM_ID,
+  getAccount,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+  setAuthority,
+  AuthorityType,
+} from '@solana/spl-token';
+import { assert } from 'chai';
+
+import { ownerWalletKeypair, payerKeypair } from './utils/users';
+import { mintNft } from './utils/nft';
+import { setupPool, stakeToken, waitForClock } from './utils/pool';
+
+// Runs against a local validator with the Token Metadata program loaded from tests/deps (see Anchor.toml)
+describe('vault-funded pool', () => {
+  const provider = anchor.Provider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Staking as Program<Staking>;
+
+  const creatorKeypair = Keypair.generate();
+
+  let rewardMint: PublicKey;
+  let rewardTokenAccount: PublicKey;
+  let funderTokenAccount: PublicKey;
+  let poolPda: PublicKey;
+  let userStakeSummaryPda: PublicKey;
+  let rewardMintAuthorityPda: PublicKey;
+  let rewardVaultPda: PublicKey;
+
+  const fundPool = async (amount: number) => {
+    await program.rpc.fundPool(new anchor.BN(amount), {
+      accounts: {
+        funder: payerKeypair.publicKey,
+        funderTokenAccount,
+        pool: poolPda,
+        rewardVault: rewardVaultPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      signers: [payerKeypair],
+    });
+  };
+
+  before(async () => {
+    // 5 reward tokens per 10 second day, with the reward mint authority kept outside the pool
+    ({
+      pool: poolPda,
+      rewardMint,
+      rewardTokenAccount,
+      rewardMintAuthority: rewardMintAuthorityPda,
+      rewardVault: rewardVaultPda,
+      userStakeSummary: userStakeSummaryPda,
+    } = await setupPool(provider, program, creatorKeypair.publicKey, {}, false));
+
+    // A reward token with a fixed supply: mint it all up front and drop the mint authority
+    funderTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        payerKeypair,
+        rewardMint,
+        payerKeypair.publicKey
+      )
+    ).address;
+
+    await mintTo(
+      provider.connection,
+      payerKeypair,
+      rewardMint,
+      funderTokenAccount,
+      payerKeypair,
+      100
+    );
+
+    await setAuthority(
+      provider.connection,
+      payerKeypair,
+      rewardMint,
+      payerKeypair,
+      AuthorityType.MintTokens,
+      null
+    );
+  });
+
+  it('Switches a pool to paying rewards from its vault', async () => {
+    await program.rpc.initRewardVault({
+      accounts: {
+        rewardVault: rewardVaultPda,
+        rewardMintAuthority: rewardMintAuthorityPda,
+        pool: poolPda,
+        rewardMint,
+        admin: ownerWalletKeypair.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      },
+      signers: [ownerWalletKeypair],
+    });
+
+    await fundPool(3);
+
+    const pool = await program.account.stakingPool.fetch(poolPda);
+    assert.ok('vault' in pool.rewardSource);
+
+    const vault = await getAccount(provider.connection, rewardVaultPda);
+    assert.equal(Number(vault.amount), 3);
+  });
+
+  it('Fails to pay a reward the vault cannot cover, then pays it once funded', async () => {
+    const nft = await mintNft(
+      provider.connection,
+      payerKeypair,
+      ownerWalletKeypair.publicKey,
+      creatorKeypair,
+      true
+    );
+
+    const { stakingAccount, vaultAccount, vaultAuthority } = await stakeToken(
+      program,
+      poolPda,
+      nft
+    );
+
+    // Wait for the one day lock to end
+    const { unstakeDate } = await program.account.stakeAccount.fetch(
+      stakingAccount
+    );
+    await waitForClock(provider.connection, unstakeDate);
+
+    const claimAccounts = {
+      rewardMintAuthority: rewardMintAuthorityPda,
+      stakingTokenOwner: ownerWalletKeypair.publicKey,
+      stakingMint: nft.mint,
+      vaultAccount,
+      vaultAuthority,
+      ownerStakingTokenAccount: nft.tokenAccount,
+      stakingAccount,
+      userStakeSummary: userStakeSummaryPda,
+      pool: poolPda,
+      rewardMint,
+      rewardVault: rewardVaultPda,
+      ownerRewardTokenAccount: rewardTokenAccount,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+
+    // The full reward of 5 is more than the vault holds
+    try {
+      await program.rpc.claimAndUnstake({
+        accounts: claimAccounts,
+        signers: [ownerWalletKeypair],
+      });
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 6014);
+    }
+
+    await fundPool(2);
+
+    await program.rpc.claimAndUnstake({
+      accounts: claimAccounts,
+      signers: [ownerWalletKeypair],
+    });
+
+    const rewards = await getAccount(provider.connection, rewardTokenAccount);
+    assert.equal(Number(rewards.amount), 5);
+
+    const vault = await getAccount(provider.connection, rewardVaultPda);
+    assert.equal(Number(vault.amount), 0);
+  });
+});